@@ -3,11 +3,14 @@
 use gmeta::{In, InOut, Metadata};
 use gstd::{prelude::*, ActorId};
 
+/// Blocks a `TransferAndCall` waits for the receiver to reply before the tokens are returned.
+pub const TRANSFER_AND_CALL_TIMEOUT: u32 = 100;
+
 pub struct FungibleTokenMetadata;
 
 impl Metadata for FungibleTokenMetadata {
     type Init = In<InitConfig>;
-    type Handle = InOut<FTAction, FTEvent>;
+    type Handle = InOut<FTAction, Result<FTEvent, FTError>>;
     type Others = ();
     type Reply = ();
     type Signal = ();
@@ -21,42 +24,367 @@ pub struct InitConfig {
     pub name: String,
     pub symbol: String,
     pub decimals: u8,
+    /// URI of the token icon.
+    pub icon: String,
+    pub description: String,
+    pub website: String,
+    pub max_supply: Option<u128>,
+    pub admin: ActorId,
+    /// Fee charged from every transfer, in basis points.
+    pub fee_bps: u16,
+    pub treasury: ActorId,
+    pub fee_exempt: Vec<ActorId>,
+    /// Makes the token a wrapped native value, minted by deposits only.
+    pub wrapped_native: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Decode, Encode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub enum Role {
+    Minter,
+    Burner,
+    Pauser,
 }
 
 #[derive(Debug, Decode, Encode, TypeInfo)]
 #[codec(crate = gstd::codec)]
 #[scale_info(crate = gstd::scale_info)]
 pub enum FTAction {
-    Mint(u128),
-    Burn(u128),
+    Mint {
+        transaction_id: Option<u64>,
+        amount: u128,
+    },
+    Burn {
+        transaction_id: Option<u64>,
+        amount: u128,
+        reason: Option<u32>,
+    },
+    BurnFrom {
+        transaction_id: Option<u64>,
+        from: ActorId,
+        amount: u128,
+        reason: Option<u32>,
+    },
     Transfer {
+        transaction_id: Option<u64>,
+        to: ActorId,
+        amount: u128,
+    },
+    TransferFrom {
+        transaction_id: Option<u64>,
+        from: ActorId,
+        to: ActorId,
+        amount: u128,
+    },
+    BatchTransfer {
+        transaction_id: Option<u64>,
+        transfers: Vec<(ActorId, u128)>,
+    },
+    /// Transfers tokens of `from` to every recipient spending the sender's allowance
+    /// for their total, either all of them or none.
+    BatchTransferFrom {
+        transaction_id: Option<u64>,
         from: ActorId,
+        transfers: Vec<(ActorId, u128)>,
+    },
+    TransferAndCall {
+        transaction_id: Option<u64>,
         to: ActorId,
         amount: u128,
+        payload: Vec<u8>,
     },
     Approve {
+        transaction_id: Option<u64>,
         to: ActorId,
         amount: u128,
     },
-    TotalSupply,
-    BalanceOf(ActorId),
+    IncreaseAllowance {
+        transaction_id: Option<u64>,
+        spender: ActorId,
+        amount: u128,
+    },
+    DecreaseAllowance {
+        transaction_id: Option<u64>,
+        spender: ActorId,
+        amount: u128,
+    },
+    Permit {
+        owner: ActorId,
+        spender: ActorId,
+        amount: u128,
+        nonce: u64,
+        deadline: u64,
+        signature: [u8; 64],
+    },
+    CreateVesting {
+        transaction_id: Option<u64>,
+        beneficiary: ActorId,
+        total: u128,
+        start: u64,
+        cliff: u64,
+        duration: u64,
+    },
+    Claim {
+        transaction_id: Option<u64>,
+        vesting_id: u64,
+    },
+    /// Mints as many tokens as the native value attached to the message.
+    Deposit,
+    /// Burns tokens and sends back as much native value.
+    Withdraw {
+        transaction_id: Option<u64>,
+        amount: u128,
+    },
+    Query(FTQuery),
+    Snapshot,
+    UpdateMetadata {
+        icon: String,
+        description: String,
+        website: String,
+    },
+    UpdateFee {
+        fee_bps: u16,
+        treasury: ActorId,
+    },
+    SetFeeExempt {
+        account: ActorId,
+        exempt: bool,
+    },
+    GrantRole {
+        role: Role,
+        account: ActorId,
+    },
+    RevokeRole {
+        role: Role,
+        account: ActorId,
+    },
+    Pause,
+    Unpause,
+    Freeze(ActorId),
+    Unfreeze(ActorId),
+    Clear {
+        account: ActorId,
+        transaction_id: u64,
+    },
 }
 
-#[derive(Debug, Encode, Decode, TypeInfo)] #[codec(crate = gstd::codec)]
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
 #[scale_info(crate = gstd::scale_info)]
 pub enum FTEvent {
+    /// `amount` is the net amount received by `to`, and `fee` is paid to the treasury.
     Transfer {
         from: ActorId,
         to: ActorId,
         amount: u128,
+        fee: u128,
+    },
+    /// `transfers` hold the recipient, the net amount it received and the fee paid
+    /// to the treasury for every leg.
+    BatchTransfer {
+        from: ActorId,
+        transfers: Vec<(ActorId, u128, u128)>,
+    },
+    Burned {
+        from: ActorId,
+        amount: u128,
+        reason: Option<u32>,
     },
     Approve {
         from: ActorId,
         to: ActorId,
         amount: u128,
     },
+    VestingCreated {
+        vesting_id: u64,
+        beneficiary: ActorId,
+        total: u128,
+    },
+    VestingClaimed {
+        vesting_id: u64,
+        beneficiary: ActorId,
+        amount: u128,
+    },
+    QueryReply(FTQueryReply),
+    Snapshot(u64),
+    Deposited {
+        account: ActorId,
+        amount: u128,
+    },
+    Withdrawn {
+        account: ActorId,
+        amount: u128,
+    },
+    MetadataUpdated {
+        icon: String,
+        description: String,
+        website: String,
+    },
+    FeeUpdated {
+        fee_bps: u16,
+        treasury: ActorId,
+    },
+    FeeExemptUpdated {
+        account: ActorId,
+        exempt: bool,
+    },
+    RoleGranted {
+        role: Role,
+        account: ActorId,
+    },
+    RoleRevoked {
+        role: Role,
+        account: ActorId,
+    },
+    Paused,
+    Unpaused,
+    Frozen(ActorId),
+    Unfrozen(ActorId),
+    TransactionCleared {
+        account: ActorId,
+        transaction_id: u64,
+    },
+}
+
+/// Message sent to the receiver of `FTAction::TransferAndCall`.
+///
+/// The receiver must reply with `true` to accept the tokens. Any other reply,
+/// or no valid reply within [`TRANSFER_AND_CALL_TIMEOUT`] blocks, returns the
+/// tokens to `from`.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct TokensReceived {
+    pub from: ActorId,
+    pub amount: u128,
+    pub payload: Vec<u8>,
+}
+
+/// Message signed by `owner` to approve `spender` without sending a transaction.
+///
+/// `nonce` must match the owner's current nonce, and the permit can't be used
+/// after the `deadline` block timestamp. `program_id` binds the signature to
+/// a single token program.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct PermitMessage {
+    pub owner: ActorId,
+    pub spender: ActorId,
+    pub amount: u128,
+    pub nonce: u64,
+    pub deadline: u64,
+    pub program_id: ActorId,
+}
+
+/// Tokens escrowed for `beneficiary` and released linearly over time.
+///
+/// All the times are block timestamps in milliseconds. Nothing is released
+/// before `start + cliff`, and everything is released at `start + duration`.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct VestingSchedule {
+    pub beneficiary: ActorId,
+    pub total: u128,
+    pub released: u128,
+    pub start: u64,
+    pub cliff: u64,
+    pub duration: u64,
+}
+
+impl VestingSchedule {
+    /// Returns the amount vested by `timestamp`, including the already released tokens.
+    pub fn vested_amount(&self, timestamp: u64) -> u128 {
+        if timestamp < self.start.saturating_add(self.cliff) {
+            return 0;
+        }
+        let elapsed = timestamp - self.start;
+        if elapsed >= self.duration {
+            return self.total;
+        }
+        let (elapsed, duration) = (elapsed as u128, self.duration as u128);
+        // Split `total * elapsed / duration` so the multiplication can't overflow.
+        self.total / duration * elapsed + self.total % duration * elapsed / duration
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub enum FTQuery {
+    Name,
+    Symbol,
+    Decimals,
+    TotalSupply,
+    BalanceOf(ActorId),
+    Allowance {
+        owner: ActorId,
+        spender: ActorId,
+    },
+    Nonce(ActorId),
+    BalanceOfAt(ActorId, u64),
+    TotalSupplyAt(u64),
+    /// Holders sorted by their ids, `limit` of them starting from `offset`.
+    Holders {
+        offset: u64,
+        limit: u64,
+    },
+    /// Allowances given by `owner` sorted by spender ids.
+    Allowances {
+        owner: ActorId,
+        offset: u64,
+        limit: u64,
+    },
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub enum FTQueryReply {
+    Name(String),
+    Symbol(String),
+    Decimals(u8),
     TotalSupply(u128),
     Balance(u128),
+    Allowance {
+        owner: ActorId,
+        spender: ActorId,
+        amount: u128,
+    },
+    Nonce(u64),
+    Holders(Vec<(ActorId, u128)>),
+    Allowances(Vec<(ActorId, u128)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub enum FTError {
+    ZeroAddress,
+    InsufficientBalance,
+    InsufficientAllowance,
+    AllowanceBelowZero,
+    Overflow,
+    MaxSupplyExceeded,
+    NotAdmin,
+    NotAllowedToClear,
+    Paused,
+    AccountFrozen(ActorId),
+    TransferRejected,
+    TransactionPending,
+    InvalidSignature,
+    InvalidNonce,
+    PermitExpired,
+    InvalidVestingSchedule,
+    VestingNotFound,
+    NothingToClaim,
+    SnapshotNotFound,
+    InvalidFee,
+    WrappedNative,
+    NotWrappedNative,
+    MissingRole(Role),
 }
 
 #[derive(Debug, Clone, Default, Encode, Decode, TypeInfo)]
@@ -65,8 +393,38 @@ pub enum FTEvent {
 pub struct IoFungibleToken {
     pub name: String,
     pub symbol: String,
+    pub icon: String,
+    pub description: String,
+    pub website: String,
     pub total_supply: u128,
+    /// Balances sorted by the holder id.
     pub balances: Vec<(ActorId, u128)>,
+    /// Allowances sorted by the owner id and then by the spender id.
     pub allowances: Vec<(ActorId, Vec<(ActorId, u128)>)>,
     pub decimals: u8,
+    pub max_supply: Option<u128>,
+    pub admin: ActorId,
+    pub roles: Vec<(Role, Vec<ActorId>)>,
+    pub transactions: Vec<((ActorId, u64), FTEvent)>,
+    pub paused: bool,
+    pub frozen: Vec<ActorId>,
+    pub vestings: Vec<(u64, VestingSchedule)>,
+    pub nonces: Vec<(ActorId, u64)>,
+    pub snapshot_id: u64,
+    /// Balance checkpoints sorted by the holder id.
+    pub account_snapshots: Vec<(ActorId, Vec<(u64, u128)>)>,
+    pub total_supply_snapshots: Vec<(u64, u128)>,
+    pub fee_bps: u16,
+    pub treasury: ActorId,
+    /// Fee exempt accounts sorted by their ids.
+    pub fee_exempt: Vec<ActorId>,
+    pub wrapped_native: bool,
+}
+
+/// Returns the value recorded as of `snapshot_id` in `checkpoints` sorted by the snapshot id.
+///
+/// `None` means the value hasn't changed since the snapshot, so the current one applies.
+pub fn checkpoint_at(checkpoints: &[(u64, u128)], snapshot_id: u64) -> Option<u128> {
+    let index = checkpoints.partition_point(|(id, _value)| *id < snapshot_id);
+    checkpoints.get(index).map(|(_id, value)| *value)
 }
@@ -1,9 +1,13 @@
 use ft_io::*;
 use gmeta::Metadata;
-use gstd::{debug, errors::Result as GstdResult, exec, msg, prelude::*, ActorId, MessageId};
-use hashbrown::HashMap;
+use gstd::{errors::Result as GstdResult, exec, msg, prelude::*, ActorId, MessageId};
+use hashbrown::{HashMap, HashSet};
 
 const ZERO_ID: ActorId = ActorId::new([0u8; 32]);
+/// Signing context used by the substrate sr25519 signatures.
+const SIGNING_CONTEXT: &[u8] = b"substrate";
+/// Transfer fee rate of 100%, in basis points.
+const MAX_FEE_BPS: u128 = 10_000;
 
 #[derive(Debug, Clone, Default)]
 struct FungibleToken {
@@ -11,155 +15,952 @@ struct FungibleToken {
     name: String,
     /// Symbol of the token.
     symbol: String,
+    /// URI of the token icon.
+    icon: String,
+    /// Description of the token.
+    description: String,
+    /// Website of the token.
+    website: String,
     /// Total supply of the token.
     total_supply: u128,
-    /// Map to hold balances of token holders.
-    balances: HashMap<ActorId, u128>,
+    /// Map to hold balances of token holders, ordered so they can be paginated.
+    balances: BTreeMap<ActorId, u128>,
     /// Map to hold allowance information of token holders.
-    allowances: HashMap<ActorId, HashMap<ActorId, u128>>,
+    allowances: BTreeMap<ActorId, BTreeMap<ActorId, u128>>,
     /// Token's decimals.
     pub decimals: u8,
+    /// Maximum amount of tokens that can ever be minted.
+    max_supply: Option<u128>,
+    /// Account allowed to grant and revoke roles.
+    admin: ActorId,
+    /// Accounts holding each of the token roles.
+    roles: HashMap<Role, HashSet<ActorId>>,
+    /// Events of the already processed transactions by their sender and id.
+    transactions: HashMap<(ActorId, u64), FTEvent>,
+    /// Transactions waiting for a reply by their sender and id.
+    pending_transactions: HashSet<(ActorId, u64)>,
+    /// Whether transfers, minting and burning are halted.
+    paused: bool,
+    /// Accounts that can neither send nor receive tokens.
+    frozen: HashSet<ActorId>,
+    /// Active vesting schedules by their ids.
+    vestings: HashMap<u64, VestingSchedule>,
+    /// Id of the next vesting schedule.
+    vesting_id: u64,
+    /// Nonces of the next permits by their owners.
+    nonces: HashMap<ActorId, u64>,
+    /// Id of the latest snapshot, zero if no snapshot was taken yet.
+    snapshot_id: u64,
+    /// Balances of the accounts as of the snapshots they were changed after.
+    account_snapshots: HashMap<ActorId, Vec<(u64, u128)>>,
+    /// Total supply as of the snapshots it was changed after.
+    total_supply_snapshots: Vec<(u64, u128)>,
+    /// Fee charged from every transfer, in basis points.
+    fee_bps: u16,
+    /// Account receiving the transfer fees.
+    treasury: ActorId,
+    /// Accounts whose transfers are free of fees.
+    fee_exempt: HashSet<ActorId>,
+    /// Whether the token wraps the native value, which always backs the total supply.
+    wrapped_native: bool,
 }
 
 static mut FUNGIBLE_TOKEN: Option<FungibleToken> = None;
 
+/// Records `value` as of `snapshot_id` unless a value was already recorded for that snapshot.
+fn update_checkpoints(checkpoints: &mut Vec<(u64, u128)>, snapshot_id: u64, value: u128) {
+    if checkpoints
+        .last()
+        .map_or(true, |(last_id, _value)| *last_id < snapshot_id)
+    {
+        checkpoints.push((snapshot_id, value));
+    }
+}
+
 impl FungibleToken {
     /// Executed on receiving `fungible-token-messages::MintInput`.
-    fn mint(&mut self, amount: u128) {
-        self.balances
-            .entry(msg::source())
-            .and_modify(|balance| *balance += amount)
-            .or_insert(amount);
-        self.total_supply += amount;
-        msg::reply(
-            FTEvent::Transfer {
-                from: ZERO_ID,
-                to: msg::source(),
-                amount,
-            },
-            0,
-        )
-        .unwrap();
+    fn mint(&mut self, amount: u128) -> Result<FTEvent, FTError> {
+        self.check_role(Role::Minter, &msg::source())?;
+        self.check_not_wrapped_native()?;
+        self.mint_tokens(&msg::source(), amount)?;
+        Ok(FTEvent::Transfer {
+            from: ZERO_ID,
+            to: msg::source(),
+            amount,
+            fee: 0,
+        })
+    }
+
+    /// Executed on receiving `fungible-token-messages::DepositInput`.
+    /// Mints as many tokens as the native value attached to the message.
+    fn deposit(&mut self, value: u128) -> Result<FTEvent, FTError> {
+        if !self.wrapped_native {
+            return Err(FTError::NotWrappedNative);
+        }
+        let account = msg::source();
+        self.mint_tokens(&account, value)?;
+        Ok(FTEvent::Deposited {
+            account,
+            amount: value,
+        })
+    }
+
+    /// Executed on receiving `fungible-token-messages::WithdrawInput`.
+    /// Burns `amount` tokens and sends back as much native value.
+    fn withdraw(&mut self, amount: u128) -> Result<FTEvent, FTError> {
+        if !self.wrapped_native {
+            return Err(FTError::NotWrappedNative);
+        }
+        let account = msg::source();
+        self.burn_tokens(&account, amount, None)?;
+        // Failing here reverts the burn along with the rest of the message.
+        msg::send_bytes(account, b"", amount).expect("Unable to send the withdrawn value");
+        Ok(FTEvent::Withdrawn { account, amount })
     }
+
+    fn mint_tokens(&mut self, to: &ActorId, amount: u128) -> Result<(), FTError> {
+        self.check_not_paused()?;
+        self.check_not_frozen(to)?;
+        let total_supply = self
+            .total_supply
+            .checked_add(amount)
+            .ok_or(FTError::Overflow)?;
+        if self
+            .max_supply
+            .map_or(false, |max_supply| total_supply > max_supply)
+        {
+            return Err(FTError::MaxSupplyExceeded);
+        }
+        let balance = self
+            .balance_of(to)
+            .checked_add(amount)
+            .ok_or(FTError::Overflow)?;
+        self.set_balance(to, balance);
+        self.set_total_supply(total_supply);
+        Ok(())
+    }
+
     /// Executed on receiving `fungible-token-messages::BurnInput`.
-    fn burn(&mut self, amount: u128) {
-        if self.balances.get(&msg::source()).unwrap_or(&0) < &amount {
-            panic!("Amount exceeds account balance");
-        }
-        self.balances
-            .entry(msg::source())
-            .and_modify(|balance| *balance -= amount);
-        self.total_supply -= amount;
-
-        msg::reply(
-            FTEvent::Transfer {
-                from: msg::source(),
-                to: ZERO_ID,
-                amount,
+    fn burn(&mut self, amount: u128, reason: Option<u32>) -> Result<FTEvent, FTError> {
+        self.check_role(Role::Burner, &msg::source())?;
+        self.check_not_wrapped_native()?;
+        self.burn_tokens(&msg::source(), amount, reason)
+    }
+
+    /// Executed on receiving `fungible-token-messages::BurnFromInput`.
+    /// Burns `amount` tokens of `from` account spending the sender's allowance.
+    fn burn_from(
+        &mut self,
+        from: &ActorId,
+        amount: u128,
+        reason: Option<u32>,
+    ) -> Result<FTEvent, FTError> {
+        self.check_role(Role::Burner, &msg::source())?;
+        self.check_not_wrapped_native()?;
+        self.check_not_frozen(&msg::source())?;
+        let allowed_amount = self.allowance(from, &msg::source());
+        if allowed_amount < amount {
+            return Err(FTError::InsufficientAllowance);
+        }
+        let event = self.burn_tokens(from, amount, reason)?;
+        self.update_allowance(from, &msg::source(), allowed_amount - amount);
+        Ok(event)
+    }
+
+    fn burn_tokens(
+        &mut self,
+        from: &ActorId,
+        amount: u128,
+        reason: Option<u32>,
+    ) -> Result<FTEvent, FTError> {
+        self.check_not_paused()?;
+        self.check_not_frozen(from)?;
+        let balance = self
+            .balance_of(from)
+            .checked_sub(amount)
+            .ok_or(FTError::InsufficientBalance)?;
+        let total_supply = self
+            .total_supply
+            .checked_sub(amount)
+            .ok_or(FTError::Overflow)?;
+        self.set_balance(from, balance);
+        self.set_total_supply(total_supply);
+        Ok(FTEvent::Burned {
+            from: *from,
+            amount,
+            reason,
+        })
+    }
+    /// Executed on receiving `fungible-token-messages::TransferInput`.
+    /// Transfers `amount` tokens from the sender account to `to` account.
+    fn transfer(&mut self, to: &ActorId, amount: u128) -> Result<FTEvent, FTError> {
+        self.transfer_tokens(&msg::source(), to, amount)
+    }
+
+    /// Executed on receiving `fungible-token-messages::TransferFromInput`.
+    /// Transfers `amount` tokens from `from` account to `to` account spending the sender's allowance.
+    fn transfer_from(
+        &mut self,
+        from: &ActorId,
+        to: &ActorId,
+        amount: u128,
+    ) -> Result<FTEvent, FTError> {
+        self.check_not_frozen(&msg::source())?;
+        let allowed_amount = self.allowance(from, &msg::source());
+        if allowed_amount < amount {
+            return Err(FTError::InsufficientAllowance);
+        }
+        let event = self.transfer_tokens(from, to, amount)?;
+        self.update_allowance(from, &msg::source(), allowed_amount - amount);
+        Ok(event)
+    }
+
+    /// Executed on receiving `fungible-token-messages::BatchTransferInput`.
+    /// Transfers tokens from the sender account to every recipient, either all of them or none.
+    fn batch_transfer(&mut self, transfers: Vec<(ActorId, u128)>) -> Result<FTEvent, FTError> {
+        self.batch_transfer_tokens(&msg::source(), transfers)
+    }
+
+    /// Executed on receiving `fungible-token-messages::BatchTransferFromInput`.
+    /// Transfers tokens from `from` account to every recipient spending the sender's allowance.
+    fn batch_transfer_from(
+        &mut self,
+        from: &ActorId,
+        transfers: Vec<(ActorId, u128)>,
+    ) -> Result<FTEvent, FTError> {
+        self.check_not_frozen(&msg::source())?;
+        let total = transfers
+            .iter()
+            .try_fold(0u128, |total, (_to, amount)| total.checked_add(*amount))
+            .ok_or(FTError::Overflow)?;
+        let allowed_amount = self.allowance(from, &msg::source());
+        if allowed_amount < total {
+            return Err(FTError::InsufficientAllowance);
+        }
+        let event = self.batch_transfer_tokens(from, transfers)?;
+        self.update_allowance(from, &msg::source(), allowed_amount - total);
+        Ok(event)
+    }
+
+    fn batch_transfer_tokens(
+        &mut self,
+        from: &ActorId,
+        transfers: Vec<(ActorId, u128)>,
+    ) -> Result<FTEvent, FTError> {
+        let from = *from;
+        self.check_not_paused()?;
+        self.check_not_frozen(&from)?;
+        let mut total: u128 = 0;
+        for (to, amount) in &transfers {
+            if to == &ZERO_ID {
+                return Err(FTError::ZeroAddress);
+            }
+            self.check_not_frozen(to)?;
+            total = total.checked_add(*amount).ok_or(FTError::Overflow)?;
+        }
+        let from_balance = self
+            .balance_of(&from)
+            .checked_sub(total)
+            .ok_or(FTError::InsufficientBalance)?;
+
+        let mut updated_balances: HashMap<ActorId, u128> = HashMap::new();
+        updated_balances.insert(from, from_balance);
+        let mut fee: u128 = 0;
+        let mut legs = Vec::with_capacity(transfers.len());
+        for (to, amount) in transfers {
+            let leg_fee = self.transfer_fee(&from, &to, amount);
+            fee += leg_fee;
+            let balance = updated_balances
+                .get(&to)
+                .copied()
+                .unwrap_or_else(|| self.balance_of(&to))
+                .checked_add(amount - leg_fee)
+                .ok_or(FTError::Overflow)?;
+            updated_balances.insert(to, balance);
+            legs.push((to, amount - leg_fee, leg_fee));
+        }
+        if fee > 0 {
+            let balance = updated_balances
+                .get(&self.treasury)
+                .copied()
+                .unwrap_or_else(|| self.balance_of(&self.treasury))
+                .checked_add(fee)
+                .ok_or(FTError::Overflow)?;
+            updated_balances.insert(self.treasury, balance);
+        }
+        for (account, balance) in updated_balances {
+            self.set_balance(&account, balance);
+        }
+
+        Ok(FTEvent::BatchTransfer {
+            from,
+            transfers: legs,
+        })
+    }
+
+    /// Executed on receiving `fungible-token-messages::ApproveInput`.
+    fn approve(&mut self, to: &ActorId, amount: u128) -> Result<FTEvent, FTError> {
+        if to == &ZERO_ID {
+            return Err(FTError::ZeroAddress);
+        }
+        Ok(self.set_allowance(&msg::source(), to, amount))
+    }
+
+    /// Executed on receiving `fungible-token-messages::IncreaseAllowanceInput`.
+    fn increase_allowance(&mut self, spender: &ActorId, amount: u128) -> Result<FTEvent, FTError> {
+        if spender == &ZERO_ID {
+            return Err(FTError::ZeroAddress);
+        }
+        let allowed_amount = self
+            .allowance(&msg::source(), spender)
+            .checked_add(amount)
+            .ok_or(FTError::Overflow)?;
+        Ok(self.set_allowance(&msg::source(), spender, allowed_amount))
+    }
+
+    /// Executed on receiving `fungible-token-messages::DecreaseAllowanceInput`.
+    fn decrease_allowance(&mut self, spender: &ActorId, amount: u128) -> Result<FTEvent, FTError> {
+        let allowed_amount = self.allowance(&msg::source(), spender);
+        if allowed_amount < amount {
+            return Err(FTError::AllowanceBelowZero);
+        }
+        Ok(self.set_allowance(&msg::source(), spender, allowed_amount - amount))
+    }
+
+    /// Executed on receiving `fungible-token-messages::TransferAndCallInput`.
+    /// Escrows `amount` tokens of the sender on the program's own account and notifies `to`
+    /// with [`TokensReceived`]. The tokens are delivered only if `to` replies with `true`
+    /// within [`TRANSFER_AND_CALL_TIMEOUT`] blocks, otherwise they're returned to the sender.
+    async fn transfer_and_call(
+        &mut self,
+        to: &ActorId,
+        amount: u128,
+        payload: Vec<u8>,
+    ) -> Result<FTEvent, FTError> {
+        let from = msg::source();
+        let program_id = exec::program_id();
+        if to == &ZERO_ID {
+            return Err(FTError::ZeroAddress);
+        }
+        self.check_not_frozen(to)?;
+        let fee = self.transfer_fee(&from, to, amount);
+        self.transfer_tokens(&from, &program_id, amount)?;
+
+        let notification = TokensReceived {
+            from,
+            amount: amount - fee,
+            payload,
+        };
+        let reply = msg::send_for_reply_as::<_, bool>(*to, notification, 0)
+            .and_then(|reply| reply.up_to(Some(TRANSFER_AND_CALL_TIMEOUT)));
+        // A receiver that fails or doesn't reply in time rejects the tokens.
+        let accepted = match reply {
+            Ok(reply) => reply.await.unwrap_or(false),
+            Err(_) => false,
+        };
+
+        // The receiver could be frozen or the token paused while waiting for the reply.
+        let delivered = if accepted {
+            self.transfer_tokens(&program_id, to, amount - fee)
+        } else {
+            Err(FTError::TransferRejected)
+        };
+        let treasury = self.treasury;
+        match delivered {
+            Ok(_) => self.move_tokens(&program_id, &treasury, fee),
+            Err(_) => self.move_tokens(&program_id, &from, amount),
+        }
+        .expect("Escrowed tokens can always be moved out");
+        delivered.map(|_| FTEvent::Transfer {
+            from,
+            to: *to,
+            amount: amount - fee,
+            fee,
+        })
+    }
+
+    /// Executed on receiving `fungible-token-messages::PermitInput`.
+    /// Sets the allowance of `spender` over `owner`'s tokens by the owner's sr25519 signature
+    /// over the SCALE-encoded [`PermitMessage`].
+    fn permit(&mut self, message: PermitMessage, signature: [u8; 64]) -> Result<FTEvent, FTError> {
+        if message.owner == ZERO_ID || message.spender == ZERO_ID {
+            return Err(FTError::ZeroAddress);
+        }
+        if exec::block_timestamp() > message.deadline {
+            return Err(FTError::PermitExpired);
+        }
+        let nonce = self.nonces.get(&message.owner).copied().unwrap_or_default();
+        if message.nonce != nonce {
+            return Err(FTError::InvalidNonce);
+        }
+
+        let owner: [u8; 32] = message.owner.into();
+        let public_key =
+            schnorrkel::PublicKey::from_bytes(&owner).map_err(|_| FTError::InvalidSignature)?;
+        let signature =
+            schnorrkel::Signature::from_bytes(&signature).map_err(|_| FTError::InvalidSignature)?;
+        public_key
+            .verify_simple(SIGNING_CONTEXT, &message.encode(), &signature)
+            .map_err(|_| FTError::InvalidSignature)?;
+
+        self.nonces.insert(message.owner, nonce + 1);
+        Ok(self.set_allowance(&message.owner, &message.spender, message.amount))
+    }
+
+    /// Executed on receiving `fungible-token-messages::CreateVestingInput`.
+    /// Escrows `total` tokens of the sender on the program's own account until they're claimed.
+    fn create_vesting(
+        &mut self,
+        beneficiary: &ActorId,
+        total: u128,
+        start: u64,
+        cliff: u64,
+        duration: u64,
+    ) -> Result<FTEvent, FTError> {
+        if beneficiary == &ZERO_ID {
+            return Err(FTError::ZeroAddress);
+        }
+        if total == 0 || duration == 0 || cliff > duration || start.checked_add(duration).is_none()
+        {
+            return Err(FTError::InvalidVestingSchedule);
+        }
+        self.transfer_tokens(&msg::source(), &exec::program_id(), total)?;
+
+        let vesting_id = self.vesting_id;
+        self.vesting_id = self.vesting_id.wrapping_add(1);
+        self.vestings.insert(
+            vesting_id,
+            VestingSchedule {
+                beneficiary: *beneficiary,
+                total,
+                released: 0,
+                start,
+                cliff,
+                duration,
             },
-            0,
-        )
-        .unwrap();
+        );
+        Ok(FTEvent::VestingCreated {
+            vesting_id,
+            beneficiary: *beneficiary,
+            total,
+        })
     }
-    /// Executed on receiving `fungible-token-messages::TransferInput` or `fungible-token-messages::TransferFromInput`.
-    /// Transfers `amount` tokens from `sender` account to `recipient` account.
-    fn transfer(&mut self, from: &ActorId, to: &ActorId, amount: u128) {
+
+    /// Executed on receiving `fungible-token-messages::ClaimInput`.
+    /// Releases the vested and not yet released tokens to the beneficiary.
+    fn claim(&mut self, vesting_id: u64) -> Result<FTEvent, FTError> {
+        let vesting = self
+            .vestings
+            .get(&vesting_id)
+            .ok_or(FTError::VestingNotFound)?;
+        let beneficiary = vesting.beneficiary;
+        let amount = vesting.vested_amount(exec::block_timestamp()) - vesting.released;
+        if amount == 0 {
+            return Err(FTError::NothingToClaim);
+        }
+        self.transfer_tokens(&exec::program_id(), &beneficiary, amount)?;
+
+        let vesting = self
+            .vestings
+            .get_mut(&vesting_id)
+            .expect("Vesting schedule is checked above");
+        vesting.released += amount;
+        if vesting.released == vesting.total {
+            self.vestings.remove(&vesting_id);
+        }
+        Ok(FTEvent::VestingClaimed {
+            vesting_id,
+            beneficiary,
+            amount,
+        })
+    }
+
+    fn transfer_tokens(
+        &mut self,
+        from: &ActorId,
+        to: &ActorId,
+        amount: u128,
+    ) -> Result<FTEvent, FTError> {
         if from == &ZERO_ID || to == &ZERO_ID {
-            panic!("Zero addresses");
+            return Err(FTError::ZeroAddress);
         };
-        if !self.can_transfer(from, amount) {
-            panic!("Not allowed to transfer")
-        }
-        if self.balances.get(from).unwrap_or(&0) < &amount {
-            panic!("Amount exceeds account balance");
-        }
-        self.balances
-            .entry(*from)
-            .and_modify(|balance| *balance -= amount);
-        self.balances
-            .entry(*to)
-            .and_modify(|balance| *balance += amount)
-            .or_insert(amount);
-        msg::reply(
-            FTEvent::Transfer {
-                from: *from,
-                to: *to,
-                amount,
+        self.check_not_paused()?;
+        self.check_not_frozen(from)?;
+        self.check_not_frozen(to)?;
+        if self.balance_of(from) < amount {
+            return Err(FTError::InsufficientBalance);
+        }
+        let fee = self.transfer_fee(from, to, amount);
+        self.move_tokens(from, to, amount - fee)?;
+        if fee > 0 {
+            let treasury = self.treasury;
+            self.move_tokens(from, &treasury, fee)?;
+        }
+        Ok(FTEvent::Transfer {
+            from: *from,
+            to: *to,
+            amount: amount - fee,
+            fee,
+        })
+    }
+
+    /// Returns the fee charged from a transfer of `amount` tokens.
+    /// Transfers to or from exempt accounts and the program itself are free.
+    fn transfer_fee(&self, from: &ActorId, to: &ActorId, amount: u128) -> u128 {
+        let program_id = exec::program_id();
+        let is_exempt =
+            |account: &ActorId| account == &program_id || self.fee_exempt.contains(account);
+        if self.fee_bps == 0 || is_exempt(from) || is_exempt(to) {
+            return 0;
+        }
+        let fee_bps = u128::from(self.fee_bps);
+        // Split `amount * fee_bps / MAX_FEE_BPS` so the multiplication can't overflow.
+        amount / MAX_FEE_BPS * fee_bps + amount % MAX_FEE_BPS * fee_bps / MAX_FEE_BPS
+    }
+
+    /// Executed on receiving `fungible-token-messages::UpdateMetadataInput`.
+    fn update_metadata(
+        &mut self,
+        icon: String,
+        description: String,
+        website: String,
+    ) -> Result<FTEvent, FTError> {
+        self.check_admin()?;
+        self.icon = icon.clone();
+        self.description = description.clone();
+        self.website = website.clone();
+        Ok(FTEvent::MetadataUpdated {
+            icon,
+            description,
+            website,
+        })
+    }
+
+    /// Executed on receiving `fungible-token-messages::UpdateFeeInput`.
+    fn update_fee(&mut self, fee_bps: u16, treasury: ActorId) -> Result<FTEvent, FTError> {
+        self.check_admin()?;
+        if u128::from(fee_bps) > MAX_FEE_BPS {
+            return Err(FTError::InvalidFee);
+        }
+        if fee_bps > 0 && treasury == ZERO_ID {
+            return Err(FTError::ZeroAddress);
+        }
+        self.fee_bps = fee_bps;
+        self.treasury = treasury;
+        Ok(FTEvent::FeeUpdated { fee_bps, treasury })
+    }
+
+    /// Executed on receiving `fungible-token-messages::SetFeeExemptInput`.
+    fn set_fee_exempt(&mut self, account: ActorId, exempt: bool) -> Result<FTEvent, FTError> {
+        self.check_admin()?;
+        if exempt {
+            self.fee_exempt.insert(account);
+        } else {
+            self.fee_exempt.remove(&account);
+        }
+        Ok(FTEvent::FeeExemptUpdated { account, exempt })
+    }
+
+    /// Moves `amount` tokens between the accounts without checking whether they're allowed to.
+    fn move_tokens(&mut self, from: &ActorId, to: &ActorId, amount: u128) -> Result<(), FTError> {
+        let from_balance = self
+            .balance_of(from)
+            .checked_sub(amount)
+            .ok_or(FTError::InsufficientBalance)?;
+        let to_balance = if from == to {
+            from_balance
+        } else {
+            self.balance_of(to)
+        }
+        .checked_add(amount)
+        .ok_or(FTError::Overflow)?;
+        self.set_balance(from, from_balance);
+        self.set_balance(to, to_balance);
+        Ok(())
+    }
+
+    fn set_allowance(&mut self, owner: &ActorId, spender: &ActorId, amount: u128) -> FTEvent {
+        self.update_allowance(owner, spender, amount);
+        FTEvent::Approve {
+            from: *owner,
+            to: *spender,
+            amount,
+        }
+    }
+
+    /// Sets the allowance of `spender`, removing it once fully consumed.
+    fn update_allowance(&mut self, owner: &ActorId, spender: &ActorId, amount: u128) {
+        if amount > 0 {
+            self.allowances
+                .entry(*owner)
+                .or_default()
+                .insert(*spender, amount);
+        } else if let Some(allowances) = self.allowances.get_mut(owner) {
+            allowances.remove(spender);
+            if allowances.is_empty() {
+                self.allowances.remove(owner);
+            }
+        }
+    }
+
+    fn query(&self, query: FTQuery) -> Result<FTQueryReply, FTError> {
+        let reply = match query {
+            FTQuery::Name => FTQueryReply::Name(self.name.clone()),
+            FTQuery::Symbol => FTQueryReply::Symbol(self.symbol.clone()),
+            FTQuery::Decimals => FTQueryReply::Decimals(self.decimals),
+            FTQuery::TotalSupply => FTQueryReply::TotalSupply(self.total_supply),
+            FTQuery::BalanceOf(account) => FTQueryReply::Balance(self.balance_of(&account)),
+            FTQuery::Allowance { owner, spender } => FTQueryReply::Allowance {
+                owner,
+                spender,
+                amount: self.allowance(&owner, &spender),
             },
-            0,
-        )
-        .unwrap();
+            FTQuery::Nonce(owner) => {
+                FTQueryReply::Nonce(self.nonces.get(&owner).copied().unwrap_or_default())
+            }
+            FTQuery::BalanceOfAt(account, snapshot_id) => {
+                self.check_snapshot_id(snapshot_id)?;
+                let checkpoints = self
+                    .account_snapshots
+                    .get(&account)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                FTQueryReply::Balance(
+                    checkpoint_at(checkpoints, snapshot_id)
+                        .unwrap_or_else(|| self.balance_of(&account)),
+                )
+            }
+            FTQuery::TotalSupplyAt(snapshot_id) => {
+                self.check_snapshot_id(snapshot_id)?;
+                FTQueryReply::TotalSupply(
+                    checkpoint_at(&self.total_supply_snapshots, snapshot_id)
+                        .unwrap_or(self.total_supply),
+                )
+            }
+            FTQuery::Holders { offset, limit } => {
+                FTQueryReply::Holders(page(&self.balances, offset, limit))
+            }
+            FTQuery::Allowances {
+                owner,
+                offset,
+                limit,
+            } => FTQueryReply::Allowances(
+                self.allowances
+                    .get(&owner)
+                    .map(|allowances| page(allowances, offset, limit))
+                    .unwrap_or_default(),
+            ),
+        };
+        Ok(reply)
     }
 
-    /// Executed on receiving `fungible-token-messages::ApproveInput`.
-    fn approve(&mut self, to: &ActorId, amount: u128) {
-        if to == &ZERO_ID {
-            panic!("Approve to zero address");
+    /// Executed on receiving `fungible-token-messages::SnapshotInput`.
+    fn snapshot(&mut self) -> Result<FTEvent, FTError> {
+        self.check_admin()?;
+        self.snapshot_id = self.snapshot_id.checked_add(1).ok_or(FTError::Overflow)?;
+        Ok(FTEvent::Snapshot(self.snapshot_id))
+    }
+
+    /// Sets the balance of `account` checkpointing the old one for the current snapshot.
+    fn set_balance(&mut self, account: &ActorId, balance: u128) {
+        if self.snapshot_id > 0 {
+            let old_balance = self.balance_of(account);
+            let checkpoints = self.account_snapshots.entry(*account).or_default();
+            update_checkpoints(checkpoints, self.snapshot_id, old_balance);
         }
+        // Empty accounts are pruned to keep the state small.
+        if balance == 0 {
+            self.balances.remove(account);
+        } else {
+            self.balances.insert(*account, balance);
+        }
+    }
+
+    /// Sets the total supply checkpointing the old one for the current snapshot.
+    fn set_total_supply(&mut self, total_supply: u128) {
+        if self.snapshot_id > 0 {
+            update_checkpoints(
+                &mut self.total_supply_snapshots,
+                self.snapshot_id,
+                self.total_supply,
+            );
+        }
+        self.total_supply = total_supply;
+    }
+
+    fn check_snapshot_id(&self, snapshot_id: u64) -> Result<(), FTError> {
+        if snapshot_id == 0 || snapshot_id > self.snapshot_id {
+            return Err(FTError::SnapshotNotFound);
+        }
+        Ok(())
+    }
+
+    fn balance_of(&self, account: &ActorId) -> u128 {
+        self.balances.get(account).copied().unwrap_or_default()
+    }
+
+    fn allowance(&self, owner: &ActorId, spender: &ActorId) -> u128 {
         self.allowances
-            .entry(msg::source())
-            .or_default()
-            .insert(*to, amount);
-        msg::reply(
-            FTEvent::Approve {
-                from: msg::source(),
-                to: *to,
-                amount,
-            },
-            0,
-        )
-        .unwrap();
+            .get(owner)
+            .and_then(|allowances| allowances.get(spender))
+            .copied()
+            .unwrap_or_default()
     }
 
-    fn can_transfer(&mut self, from: &ActorId, amount: u128) -> bool {
-        if from == &msg::source()
-            || from == &exec::origin()
-            || self.balances.get(&msg::source()).unwrap_or(&0) >= &amount
-        {
-            return true;
+    /// Executed on receiving `fungible-token-messages::GrantRoleInput`.
+    fn grant_role(&mut self, role: Role, account: &ActorId) -> Result<FTEvent, FTError> {
+        self.check_admin()?;
+        if account == &ZERO_ID {
+            return Err(FTError::ZeroAddress);
+        }
+        self.roles.entry(role).or_default().insert(*account);
+        Ok(FTEvent::RoleGranted {
+            role,
+            account: *account,
+        })
+    }
+
+    /// Executed on receiving `fungible-token-messages::RevokeRoleInput`.
+    fn revoke_role(&mut self, role: Role, account: &ActorId) -> Result<FTEvent, FTError> {
+        self.check_admin()?;
+        if let Some(accounts) = self.roles.get_mut(&role) {
+            accounts.remove(account);
+        }
+        Ok(FTEvent::RoleRevoked {
+            role,
+            account: *account,
+        })
+    }
+
+    /// Executed on receiving `fungible-token-messages::PauseInput`.
+    fn pause(&mut self) -> Result<FTEvent, FTError> {
+        self.check_role(Role::Pauser, &msg::source())?;
+        self.paused = true;
+        Ok(FTEvent::Paused)
+    }
+
+    /// Executed on receiving `fungible-token-messages::UnpauseInput`.
+    fn unpause(&mut self) -> Result<FTEvent, FTError> {
+        self.check_role(Role::Pauser, &msg::source())?;
+        self.paused = false;
+        Ok(FTEvent::Unpaused)
+    }
+
+    /// Executed on receiving `fungible-token-messages::FreezeInput`.
+    fn freeze(&mut self, account: ActorId) -> Result<FTEvent, FTError> {
+        self.check_admin()?;
+        self.frozen.insert(account);
+        Ok(FTEvent::Frozen(account))
+    }
+
+    /// Executed on receiving `fungible-token-messages::UnfreezeInput`.
+    fn unfreeze(&mut self, account: ActorId) -> Result<FTEvent, FTError> {
+        self.check_admin()?;
+        self.frozen.remove(&account);
+        Ok(FTEvent::Unfrozen(account))
+    }
+
+    fn process_transaction(
+        &mut self,
+        transaction_id: Option<u64>,
+        action: impl FnOnce(&mut FungibleToken) -> Result<FTEvent, FTError>,
+    ) -> Result<FTEvent, FTError> {
+        if let Some(ft_event) = self.cached_transaction(transaction_id) {
+            Ok(ft_event)
+        } else {
+            let ft_event = action(self)?;
+
+            self.cache_transaction(transaction_id, &ft_event);
+
+            Ok(ft_event)
         }
-        if let Some(allowed_amount) = self
-            .allowances
-            .get(from)
-            .and_then(|m| m.get(&msg::source()))
+    }
+
+    fn cached_transaction(&self, transaction_id: Option<u64>) -> Option<FTEvent> {
+        let transaction_id = transaction_id?;
+        self.transactions
+            .get(&(msg::source(), transaction_id))
+            .cloned()
+    }
+
+    fn cache_transaction(&mut self, transaction_id: Option<u64>, ft_event: &FTEvent) {
+        if let Some(transaction_id) = transaction_id {
+            self.transactions
+                .insert((msg::source(), transaction_id), ft_event.clone());
+        }
+    }
+
+    /// Marks the transaction as waiting for a reply, so it can't be retried meanwhile.
+    /// Returns `false` if the transaction is already pending.
+    fn start_transaction(&mut self, transaction_id: Option<u64>) -> bool {
+        transaction_id.map_or(true, |transaction_id| {
+            self.pending_transactions
+                .insert((msg::source(), transaction_id))
+        })
+    }
+
+    fn finish_transaction(
+        &mut self,
+        transaction_id: Option<u64>,
+        result: &Result<FTEvent, FTError>,
+    ) {
+        if let Some(transaction_id) = transaction_id {
+            self.pending_transactions
+                .remove(&(msg::source(), transaction_id));
+        }
+        if let Ok(ft_event) = result {
+            self.cache_transaction(transaction_id, ft_event);
+        }
+    }
+
+    /// Executed on receiving `fungible-token-messages::ClearInput`.
+    fn clear(&mut self, account: ActorId, transaction_id: u64) -> Result<FTEvent, FTError> {
+        if msg::source() != exec::program_id() {
+            return Err(FTError::NotAllowedToClear);
+        }
+        self.transactions.remove(&(account, transaction_id));
+        Ok(FTEvent::TransactionCleared {
+            account,
+            transaction_id,
+        })
+    }
+
+    fn check_admin(&self) -> Result<(), FTError> {
+        if msg::source() != self.admin {
+            return Err(FTError::NotAdmin);
+        }
+        Ok(())
+    }
+
+    /// The supply of a wrapped native token changes only by deposits and withdrawals.
+    fn check_not_wrapped_native(&self) -> Result<(), FTError> {
+        if self.wrapped_native {
+            return Err(FTError::WrappedNative);
+        }
+        Ok(())
+    }
+
+    fn check_not_paused(&self) -> Result<(), FTError> {
+        if self.paused {
+            return Err(FTError::Paused);
+        }
+        Ok(())
+    }
+
+    fn check_not_frozen(&self, account: &ActorId) -> Result<(), FTError> {
+        if self.frozen.contains(account) {
+            return Err(FTError::AccountFrozen(*account));
+        }
+        Ok(())
+    }
+
+    fn check_role(&self, role: Role, account: &ActorId) -> Result<(), FTError> {
+        if !self
+            .roles
+            .get(&role)
+            .map_or(false, |accounts| accounts.contains(account))
         {
-            if allowed_amount >= &amount {
-                self.allowances.entry(*from).and_modify(|m| {
-                    m.entry(msg::source()).and_modify(|a| *a -= amount);
-                });
-                return true;
-            }
+            return Err(FTError::MissingRole(role));
         }
-        false
+        Ok(())
     }
 }
 
 fn common_state() -> <FungibleTokenMetadata as Metadata>::State {
-    let state = static_mut_state();
     let FungibleToken {
         name,
         symbol,
+        icon,
+        description,
+        website,
         total_supply,
         balances,
         allowances,
         decimals,
-    } = state.clone();
+        max_supply,
+        admin,
+        roles,
+        transactions,
+        pending_transactions: _,
+        paused,
+        frozen,
+        vestings,
+        vesting_id: _,
+        nonces,
+        snapshot_id,
+        account_snapshots,
+        total_supply_snapshots,
+        fee_bps,
+        treasury,
+        fee_exempt,
+        wrapped_native,
+    } = static_mut_state();
 
+    // The maps are ordered by the ids, so the metawasm can look them up by binary search.
     let balances = balances.iter().map(|(k, v)| (*k, *v)).collect();
     let allowances = allowances
         .iter()
         .map(|(id, allowance)| (*id, allowance.iter().map(|(k, v)| (*k, *v)).collect()))
         .collect();
+    let roles = roles
+        .iter()
+        .map(|(role, accounts)| (*role, accounts.iter().copied().collect()))
+        .collect();
+    let transactions = transactions
+        .iter()
+        .map(|(key, event)| (*key, event.clone()))
+        .collect();
+    let frozen = frozen.iter().copied().collect();
+    let vestings = vestings
+        .iter()
+        .map(|(id, vesting)| (*id, vesting.clone()))
+        .collect();
+    let nonces = nonces.iter().map(|(k, v)| (*k, *v)).collect();
+    let mut account_snapshots: Vec<_> = account_snapshots
+        .iter()
+        .map(|(id, checkpoints)| (*id, checkpoints.clone()))
+        .collect();
+    account_snapshots.sort_unstable_by_key(|(id, _checkpoints)| *id);
+    let mut fee_exempt: Vec<_> = fee_exempt.iter().copied().collect();
+    fee_exempt.sort_unstable();
     IoFungibleToken {
-        name,
-        symbol,
-        total_supply,
+        name: name.clone(),
+        symbol: symbol.clone(),
+        icon: icon.clone(),
+        description: description.clone(),
+        website: website.clone(),
+        total_supply: *total_supply,
         balances,
         allowances,
-        decimals,
+        decimals: *decimals,
+        max_supply: *max_supply,
+        admin: *admin,
+        roles,
+        transactions,
+        paused: *paused,
+        frozen,
+        vestings,
+        nonces,
+        snapshot_id: *snapshot_id,
+        account_snapshots,
+        total_supply_snapshots: total_supply_snapshots.clone(),
+        fee_bps: *fee_bps,
+        treasury: *treasury,
+        fee_exempt,
+        wrapped_native: *wrapped_native,
     }
 }
 
+/// Returns the page of `entries` sorted by their ids.
+fn page(entries: &BTreeMap<ActorId, u128>, offset: u64, limit: u64) -> Vec<(ActorId, u128)> {
+    entries
+        .iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .map(|(id, amount)| (*id, *amount))
+        .collect()
+}
+
 fn static_mut_state() -> &'static mut FungibleToken {
     unsafe { FUNGIBLE_TOKEN.get_or_insert(Default::default()) }
 }
@@ -180,82 +981,177 @@ fn reply(payload: impl Encode) -> GstdResult<MessageId> {
     msg::reply(payload, 0)
 }
 
-#[no_mangle]
-extern "C" fn handle() {
+#[gstd::async_main]
+async fn main() {
     let action: FTAction = msg::load().expect("Could not load Action");
+    let value = msg::value();
     let ft: &mut FungibleToken = unsafe { FUNGIBLE_TOKEN.get_or_insert(Default::default()) };
-    match action {
-        FTAction::Mint(amount) => {
-            ft.mint(amount);
-        }
-        FTAction::Burn(amount) => {
-            ft.burn(amount);
-        }
-        FTAction::Transfer { from, to, amount } => {
-            ft.transfer(&from, &to, amount);
-        }
-        FTAction::Approve { to, amount } => {
-            ft.approve(&to, amount);
-        }
-        FTAction::TotalSupply => {
-            msg::reply(FTEvent::TotalSupply(ft.total_supply), 0).unwrap();
-        }
-        FTAction::BalanceOf(account) => {
-            let balance = ft.balances.get(&account).unwrap_or(&0);
-            msg::reply(FTEvent::Balance(*balance), 0).unwrap();
+    let result = match action {
+        FTAction::Mint {
+            transaction_id,
+            amount,
+        } => ft.process_transaction(transaction_id, |ft| ft.mint(amount)),
+        FTAction::Burn {
+            transaction_id,
+            amount,
+            reason,
+        } => ft.process_transaction(transaction_id, |ft| ft.burn(amount, reason)),
+        FTAction::BurnFrom {
+            transaction_id,
+            from,
+            amount,
+            reason,
+        } => ft.process_transaction(transaction_id, |ft| ft.burn_from(&from, amount, reason)),
+        FTAction::Transfer {
+            transaction_id,
+            to,
+            amount,
+        } => ft.process_transaction(transaction_id, |ft| ft.transfer(&to, amount)),
+        FTAction::TransferFrom {
+            transaction_id,
+            from,
+            to,
+            amount,
+        } => ft.process_transaction(transaction_id, |ft| ft.transfer_from(&from, &to, amount)),
+        FTAction::BatchTransfer {
+            transaction_id,
+            transfers,
+        } => ft.process_transaction(transaction_id, |ft| ft.batch_transfer(transfers)),
+        FTAction::BatchTransferFrom {
+            transaction_id,
+            from,
+            transfers,
+        } => ft.process_transaction(transaction_id, |ft| {
+            ft.batch_transfer_from(&from, transfers)
+        }),
+        FTAction::Approve {
+            transaction_id,
+            to,
+            amount,
+        } => ft.process_transaction(transaction_id, |ft| ft.approve(&to, amount)),
+        FTAction::IncreaseAllowance {
+            transaction_id,
+            spender,
+            amount,
+        } => ft.process_transaction(transaction_id, |ft| ft.increase_allowance(&spender, amount)),
+        FTAction::DecreaseAllowance {
+            transaction_id,
+            spender,
+            amount,
+        } => ft.process_transaction(transaction_id, |ft| ft.decrease_allowance(&spender, amount)),
+        FTAction::TransferAndCall {
+            transaction_id,
+            to,
+            amount,
+            payload,
+        } => {
+            if let Some(ft_event) = ft.cached_transaction(transaction_id) {
+                Ok(ft_event)
+            } else if !ft.start_transaction(transaction_id) {
+                Err(FTError::TransactionPending)
+            } else {
+                let result = ft.transfer_and_call(&to, amount, payload).await;
+                ft.finish_transaction(transaction_id, &result);
+                result
+            }
         }
-    }
+        FTAction::Permit {
+            owner,
+            spender,
+            amount,
+            nonce,
+            deadline,
+            signature,
+        } => ft.permit(
+            PermitMessage {
+                owner,
+                spender,
+                amount,
+                nonce,
+                deadline,
+                program_id: exec::program_id(),
+            },
+            signature,
+        ),
+        FTAction::CreateVesting {
+            transaction_id,
+            beneficiary,
+            total,
+            start,
+            cliff,
+            duration,
+        } => ft.process_transaction(transaction_id, |ft| {
+            ft.create_vesting(&beneficiary, total, start, cliff, duration)
+        }),
+        FTAction::Claim {
+            transaction_id,
+            vesting_id,
+        } => ft.process_transaction(transaction_id, |ft| ft.claim(vesting_id)),
+        FTAction::Deposit => ft.deposit(value),
+        FTAction::Withdraw {
+            transaction_id,
+            amount,
+        } => ft.process_transaction(transaction_id, |ft| ft.withdraw(amount)),
+        FTAction::Query(query) => ft.query(query).map(FTEvent::QueryReply),
+        FTAction::Snapshot => ft.snapshot(),
+        FTAction::UpdateMetadata {
+            icon,
+            description,
+            website,
+        } => ft.update_metadata(icon, description, website),
+        FTAction::UpdateFee { fee_bps, treasury } => ft.update_fee(fee_bps, treasury),
+        FTAction::SetFeeExempt { account, exempt } => ft.set_fee_exempt(account, exempt),
+        FTAction::GrantRole { role, account } => ft.grant_role(role, &account),
+        FTAction::RevokeRole { role, account } => ft.revoke_role(role, &account),
+        FTAction::Pause => ft.pause(),
+        FTAction::Unpause => ft.unpause(),
+        FTAction::Freeze(account) => ft.freeze(account),
+        FTAction::Unfreeze(account) => ft.unfreeze(account),
+        FTAction::Clear {
+            account,
+            transaction_id,
+        } => ft.clear(account, transaction_id),
+    };
+    // The attached value is kept only when it was wrapped by a deposit.
+    let refund = if matches!(result, Ok(FTEvent::Deposited { .. })) {
+        0
+    } else {
+        value
+    };
+    msg::reply(result, refund).expect("Failed to encode or reply with `Result<FTEvent, FTError>`");
 }
 
 #[no_mangle]
 extern "C" fn init() {
     let config: InitConfig = msg::load().expect("Unable to decode InitConfig");
+    if config.admin == ZERO_ID {
+        panic!("Admin can't be zero address");
+    }
+    if u128::from(config.fee_bps) > MAX_FEE_BPS {
+        panic!("Fee can't exceed 100%");
+    }
+    if config.fee_bps > 0 && config.treasury == ZERO_ID {
+        panic!("Treasury can't be zero address");
+    }
+    let mut roles: HashMap<Role, HashSet<ActorId>> = HashMap::new();
+    for role in [Role::Minter, Role::Burner, Role::Pauser] {
+        roles.entry(role).or_default().insert(config.admin);
+    }
     let ft = FungibleToken {
         name: config.name,
         symbol: config.symbol,
+        icon: config.icon,
+        description: config.description,
+        website: config.website,
         decimals: config.decimals,
+        max_supply: config.max_supply,
+        admin: config.admin,
+        roles,
+        fee_bps: config.fee_bps,
+        treasury: config.treasury,
+        fee_exempt: config.fee_exempt.into_iter().collect(),
+        wrapped_native: config.wrapped_native,
         ..Default::default()
     };
     unsafe { FUNGIBLE_TOKEN = Some(ft) };
 }
-
-#[no_mangle]
-extern "C" fn meta_state() -> *mut [i32; 2] {
-    let query: State = msg::load().expect("failed to decode input argument");
-    let ft: &mut FungibleToken = unsafe { FUNGIBLE_TOKEN.get_or_insert(Default::default()) };
-    debug!("{:?}", query);
-    let encoded = match query {
-        State::Name => StateReply::Name(ft.name.clone()),
-        State::Symbol => StateReply::Name(ft.symbol.clone()),
-        State::Decimals => StateReply::Decimals(ft.decimals),
-        State::TotalSupply => StateReply::TotalSupply(ft.total_supply),
-        State::BalanceOf(account) => {
-            let balance = ft.balances.get(&account).unwrap_or(&0);
-            StateReply::Balance(*balance)
-        }
-    }
-    .encode();
-    gstd::util::to_leak_ptr(encoded)
-}
-
-#[derive(Debug, Encode, Decode, TypeInfo)]
-#[codec(crate = gstd::codec)]
-#[scale_info(crate = gstd::scale_info)]
-pub enum State {
-    Name,
-    Symbol,
-    Decimals,
-    TotalSupply,
-    BalanceOf(ActorId),
-}
-
-#[derive(Debug, Encode, Decode, TypeInfo)]
-#[codec(crate = gstd::codec)]
-#[scale_info(crate = gstd::scale_info)]
-pub enum StateReply {
-    Name(String),
-    Symbol(String),
-    Decimals(u8),
-    TotalSupply(u128),
-    Balance(u128),
-}
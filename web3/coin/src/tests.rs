@@ -1,7 +1,17 @@
 use ft_io::*;
-use gstd::{Encode, String};
-use gtest::{Program, System};
+use gstd::{prelude::*, ActorId, Decode, Encode};
+use gtest::{Program, System, WasmProgram};
+use schnorrkel::{ExpansionMode, Keypair, MiniSecretKey};
 const USERS: &[u64] = &[3, 4, 5];
+const RECEIVER: u64 = 10;
+
+fn ok(event: FTEvent) -> Vec<u8> {
+    Ok::<FTEvent, FTError>(event).encode()
+}
+
+fn err(error: FTError) -> Vec<u8> {
+    Err::<FTEvent, FTError>(error).encode()
+}
 
 fn init_with_mint(sys: &System) {
     sys.init_logger();
@@ -14,20 +24,35 @@ fn init_with_mint(sys: &System) {
             name: String::from("MyToken"),
             symbol: String::from("MTK"),
             decimals: 18,
+            icon: String::from("ipfs://icon"),
+            description: String::from("Marketplace currency"),
+            website: String::from("https://example.com"),
+            max_supply: None,
+            admin: USERS[0].into(),
+            fee_bps: 0,
+            treasury: 0.into(),
+            fee_exempt: vec![],
+            wrapped_native: false,
         },
     );
 
     assert!(res.log().is_empty());
 
-    let res = ft.send(USERS[0], FTAction::Mint(1000000));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 1000000,
+        },
+    );
     assert!(res.contains(&(
         USERS[0],
-        FTEvent::Transfer {
+        ok(FTEvent::Transfer {
             from: 0.into(),
             to: USERS[0].into(),
             amount: 1000000,
-        }
-        .encode()
+            fee: 0,
+        })
     )));
 }
 
@@ -36,154 +61,2438 @@ fn mint() {
     let sys = System::new();
     init_with_mint(&sys);
     let ft = sys.get_program(1);
-    let res = ft.send(USERS[0], FTAction::BalanceOf(USERS[0].into()));
-    assert!(res.contains(&(USERS[0], FTEvent::Balance(1000000).encode())));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(1000000)))
+    )));
 }
 
 #[test]
-fn burn() {
+fn query() {
     let sys = System::new();
     init_with_mint(&sys);
     let ft = sys.get_program(1);
-    let res = ft.send(USERS[0], FTAction::Burn(1000));
+    let res = ft.send(USERS[1], FTAction::Query(FTQuery::Name));
     assert!(res.contains(&(
-        USERS[0],
-        FTEvent::Transfer {
-            from: USERS[0].into(),
-            to: 0.into(),
-            amount: 1000,
-        }
-        .encode()
+        USERS[1],
+        ok(FTEvent::QueryReply(FTQueryReply::Name(String::from(
+            "MyToken"
+        ))))
+    )));
+    let res = ft.send(USERS[1], FTAction::Query(FTQuery::Symbol));
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::QueryReply(FTQueryReply::Symbol(String::from(
+            "MTK"
+        ))))
+    )));
+    let res = ft.send(USERS[1], FTAction::Query(FTQuery::Decimals));
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::QueryReply(FTQueryReply::Decimals(18)))
+    )));
+    let res = ft.send(USERS[1], FTAction::Query(FTQuery::TotalSupply));
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::QueryReply(FTQueryReply::TotalSupply(1000000)))
+    )));
+    let res = ft.send(
+        USERS[1],
+        FTAction::Query(FTQuery::BalanceOf(USERS[2].into())),
+    );
+    assert!(res.contains(&(USERS[1], ok(FTEvent::QueryReply(FTQueryReply::Balance(0))))));
+    let res = ft.send(
+        USERS[1],
+        FTAction::Query(FTQuery::Allowance {
+            owner: USERS[0].into(),
+            spender: USERS[1].into(),
+        }),
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::QueryReply(FTQueryReply::Allowance {
+            owner: USERS[0].into(),
+            spender: USERS[1].into(),
+            amount: 0,
+        }))
     )));
-    let res = ft.send(USERS[0], FTAction::BalanceOf(USERS[0].into()));
-    assert!(res.contains(&(USERS[0], FTEvent::Balance(999000).encode())));
 }
 
 #[test]
-fn burn_failures() {
+fn paginated_queries() {
     let sys = System::new();
-    sys.init_logger();
     init_with_mint(&sys);
     let ft = sys.get_program(1);
-    // must fail since the amount > the user balance
-    let res = ft.send(USERS[0], FTAction::Burn(1000001));
-    assert!(res.main_failed());
+    for user in &USERS[1..] {
+        ft.send(
+            USERS[0],
+            FTAction::Transfer {
+                transaction_id: None,
+                to: (*user).into(),
+                amount: 100,
+            },
+        );
+    }
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::Holders {
+            offset: 1,
+            limit: 1,
+        }),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Holders(vec![(
+            USERS[1].into(),
+            100
+        )])))
+    )));
+
+    // the emptied account must be pruned from the holders
+    ft.send(
+        USERS[1],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[2].into(),
+            amount: 100,
+        },
+    );
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::Holders {
+            offset: 0,
+            limit: 10,
+        }),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Holders(vec![
+            (USERS[0].into(), 999800),
+            (USERS[2].into(), 200),
+        ])))
+    )));
+
+    for user in &USERS[1..] {
+        ft.send(
+            USERS[0],
+            FTAction::Approve {
+                transaction_id: None,
+                to: (*user).into(),
+                amount: 50,
+            },
+        );
+    }
+    // the fully consumed allowance must be pruned
+    ft.send(
+        USERS[1],
+        FTAction::TransferFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 50,
+        },
+    );
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::Allowances {
+            owner: USERS[0].into(),
+            offset: 0,
+            limit: 10,
+        }),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Allowances(vec![(
+            USERS[2].into(),
+            50
+        )])))
+    )));
+}
+
+/// Calls the `function` state function of the `ft-state` metawasm.
+fn read_state<A: Encode, T: Decode>(ft: &Program, function: &str, argument: A) -> T {
+    ft.read_state_using_wasm(function, ft_state::WASM_BINARY.into(), Some(argument))
+        .expect("Unable to read the state")
 }
 
 #[test]
-fn transfer() {
+fn state_balances() {
     let sys = System::new();
     init_with_mint(&sys);
     let ft = sys.get_program(1);
     let res = ft.send(
         USERS[0],
         FTAction::Transfer {
-            from: USERS[0].into(),
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 1000,
+        },
+    );
+    assert!(!res.main_failed());
+    let res = ft.send(
+        USERS[0],
+        FTAction::Approve {
+            transaction_id: None,
             to: USERS[1].into(),
             amount: 500,
         },
     );
+    assert!(!res.main_failed());
+
+    let balance: u128 = read_state(&ft, "balances_of", ActorId::from(USERS[0]));
+    assert_eq!(balance, 999000);
+    let balance: u128 = read_state(&ft, "balances_of", ActorId::from(USERS[1]));
+    assert_eq!(balance, 1000);
+    // unknown holders have an empty balance
+    let balance: u128 = read_state(&ft, "balances_of", ActorId::from(USERS[2]));
+    assert_eq!(balance, 0);
+
+    let allowance: u128 = read_state(
+        &ft,
+        "allowance",
+        (ActorId::from(USERS[0]), ActorId::from(USERS[1])),
+    );
+    assert_eq!(allowance, 500);
+    // unknown owners and spenders have an empty allowance
+    let allowance: u128 = read_state(
+        &ft,
+        "allowance",
+        (ActorId::from(USERS[0]), ActorId::from(USERS[2])),
+    );
+    assert_eq!(allowance, 0);
+    let allowance: u128 = read_state(
+        &ft,
+        "allowance",
+        (ActorId::from(USERS[1]), ActorId::from(USERS[0])),
+    );
+    assert_eq!(allowance, 0);
+}
+
+#[test]
+fn state_holders() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    for (user, amount) in [(USERS[1], 300), (USERS[2], 200)] {
+        let res = ft.send(
+            USERS[0],
+            FTAction::Transfer {
+                transaction_id: None,
+                to: user.into(),
+                amount,
+            },
+        );
+        assert!(!res.main_failed());
+    }
+
+    let holders: Vec<(ActorId, u128)> = read_state(&ft, "holders", (0u64, 10u64));
+    assert_eq!(
+        holders,
+        vec![
+            (USERS[0].into(), 999500),
+            (USERS[1].into(), 300),
+            (USERS[2].into(), 200),
+        ]
+    );
+    let holders: Vec<(ActorId, u128)> = read_state(&ft, "holders", (1u64, 1u64));
+    assert_eq!(holders, vec![(USERS[1].into(), 300)]);
+    // the page is cut at the last holder
+    let holders: Vec<(ActorId, u128)> = read_state(&ft, "holders", (2u64, 10u64));
+    assert_eq!(holders, vec![(USERS[2].into(), 200)]);
+    let holders: Vec<(ActorId, u128)> = read_state(&ft, "holders", (3u64, 10u64));
+    assert!(holders.is_empty());
+    let holders: Vec<(ActorId, u128)> = read_state(&ft, "holders", (0u64, 0u64));
+    assert!(holders.is_empty());
+
+    // USERS[2] becomes the second largest holder
+    let res = ft.send(
+        USERS[1],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[2].into(),
+            amount: 250,
+        },
+    );
+    assert!(!res.main_failed());
+    let holders: Vec<(ActorId, u128)> = read_state(&ft, "top_holders", 2u64);
+    assert_eq!(
+        holders,
+        vec![(USERS[0].into(), 999500), (USERS[2].into(), 450)]
+    );
+    let holders: Vec<(ActorId, u128)> = read_state(&ft, "top_holders", 10u64);
+    assert_eq!(
+        holders,
+        vec![
+            (USERS[0].into(), 999500),
+            (USERS[2].into(), 450),
+            (USERS[1].into(), 50),
+        ]
+    );
+}
+
+#[test]
+fn update_metadata() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    let update = || FTAction::UpdateMetadata {
+        icon: String::from("ipfs://new-icon"),
+        description: String::from("New description"),
+        website: String::from("https://example.org"),
+    };
 
+    // must fail since only the admin can update the metadata
+    let res = ft.send(USERS[1], update());
+    assert!(res.contains(&(USERS[1], err(FTError::NotAdmin))));
+
+    let res = ft.send(USERS[0], update());
     assert!(res.contains(&(
         USERS[0],
-        FTEvent::Transfer {
-            from: USERS[0].into(),
-            to: USERS[1].into(),
-            amount: 500,
-        }
-        .encode()
+        ok(FTEvent::MetadataUpdated {
+            icon: String::from("ipfs://new-icon"),
+            description: String::from("New description"),
+            website: String::from("https://example.org"),
+        })
     )));
+}
 
-    // check that the balance of `USER[0]` decreased and the balance of `USER[1]` increased
-    let res = ft.send(USERS[0], FTAction::BalanceOf(USERS[0].into()));
-    assert!(res.contains(&(USERS[0], FTEvent::Balance(999500).encode())));
-    let res = ft.send(USERS[0], FTAction::BalanceOf(USERS[1].into()));
-    assert!(res.contains(&(USERS[0], FTEvent::Balance(500).encode())));
+#[test]
+fn mint_failures() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    // must fail since `USERS[1]` doesn't have the minter role
+    let res = ft.send(
+        USERS[1],
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 1000,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::MissingRole(Role::Minter)))));
 }
 
 #[test]
-fn transfer_failures() {
+fn mint_overflow() {
     let sys = System::new();
     init_with_mint(&sys);
     let ft = sys.get_program(1);
-    //must fail since the amount > balance
+    // must fail since the total supply would overflow
     let res = ft.send(
         USERS[0],
-        FTAction::Transfer {
-            from: USERS[0].into(),
-            to: USERS[1].into(),
-            amount: 2000000,
+        FTAction::Mint {
+            transaction_id: None,
+            amount: u128::MAX,
         },
     );
-    assert!(res.main_failed());
+    assert!(res.contains(&(USERS[0], err(FTError::Overflow))));
+    let res = ft.send(USERS[0], FTAction::Query(FTQuery::TotalSupply));
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::TotalSupply(1000000)))
+    )));
+}
 
-    //must fail transfer to zero address
+#[test]
+fn mint_max_supply() {
+    let sys = System::new();
+    sys.init_logger();
+    let ft = Program::current(&sys);
     let res = ft.send(
-        USERS[2],
-        FTAction::Transfer {
-            from: USERS[0].into(),
-            to: 0.into(),
-            amount: 100,
+        USERS[0],
+        InitConfig {
+            name: String::from("MyToken"),
+            symbol: String::from("MTK"),
+            decimals: 18,
+            icon: String::from("ipfs://icon"),
+            description: String::from("Marketplace currency"),
+            website: String::from("https://example.com"),
+            max_supply: Some(1000),
+            admin: USERS[0].into(),
+            fee_bps: 0,
+            treasury: 0.into(),
+            fee_exempt: vec![],
+            wrapped_native: false,
+        },
+    );
+    assert!(res.log().is_empty());
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 1000,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Transfer {
+            from: 0.into(),
+            to: USERS[0].into(),
+            amount: 1000,
+            fee: 0,
+        })
+    )));
+
+    // must fail since the max supply is reached
+    let res = ft.send(
+        USERS[0],
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 1,
         },
     );
-    assert!(res.main_failed());
+    assert!(res.contains(&(USERS[0], err(FTError::MaxSupplyExceeded))));
 }
 
 #[test]
-fn approve_and_transfer() {
+fn grant_and_revoke_role() {
     let sys = System::new();
     init_with_mint(&sys);
     let ft = sys.get_program(1);
 
+    // must fail since only the admin can grant roles
+    let res = ft.send(
+        USERS[1],
+        FTAction::GrantRole {
+            role: Role::Minter,
+            account: USERS[1].into(),
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::NotAdmin))));
+
     let res = ft.send(
         USERS[0],
-        FTAction::Approve {
-            to: USERS[1].into(),
-            amount: 500,
+        FTAction::GrantRole {
+            role: Role::Minter,
+            account: USERS[1].into(),
         },
     );
     assert!(res.contains(&(
         USERS[0],
-        FTEvent::Approve {
-            from: USERS[0].into(),
-            to: USERS[1].into(),
-            amount: 500,
-        }
-        .encode()
+        ok(FTEvent::RoleGranted {
+            role: Role::Minter,
+            account: USERS[1].into(),
+        })
     )));
 
     let res = ft.send(
         USERS[1],
-        FTAction::Transfer {
-            from: USERS[0].into(),
-            to: USERS[2].into(),
-            amount: 200,
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 1000,
         },
     );
     assert!(res.contains(&(
         USERS[1],
-        FTEvent::Transfer {
-            from: USERS[0].into(),
-            to: USERS[2].into(),
-            amount: 200,
-        }
-        .encode()
+        ok(FTEvent::Transfer {
+            from: 0.into(),
+            to: USERS[1].into(),
+            amount: 1000,
+            fee: 0,
+        })
     )));
 
-    // check that the balance of `USER[0]` decreased and the balance of `USER[1]` increased
-    let res = ft.send(USERS[0], FTAction::BalanceOf(USERS[0].into()));
-    assert!(res.contains(&(USERS[0], FTEvent::Balance(999800).encode())));
-    let res = ft.send(USERS[0], FTAction::BalanceOf(USERS[2].into()));
-    assert!(res.contains(&(USERS[0], FTEvent::Balance(200).encode())));
+    let res = ft.send(
+        USERS[0],
+        FTAction::RevokeRole {
+            role: Role::Minter,
+            account: USERS[1].into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::RoleRevoked {
+            role: Role::Minter,
+            account: USERS[1].into(),
+        })
+    )));
 
-    // must fail since not enough allowance
+    // must fail since the minter role was revoked
     let res = ft.send(
         USERS[1],
-        FTAction::Transfer {
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 1000,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::MissingRole(Role::Minter)))));
+}
+
+#[test]
+fn burn() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    let res = ft.send(
+        USERS[0],
+        FTAction::Burn {
+            transaction_id: None,
+            amount: 1000,
+            reason: None,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Burned {
             from: USERS[0].into(),
-            to: USERS[2].into(),
-            amount: 800,
+            amount: 1000,
+            reason: None,
+        })
+    )));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(999000)))
+    )));
+}
+
+#[test]
+fn burn_failures() {
+    let sys = System::new();
+    sys.init_logger();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    // must fail since the amount > the user balance
+    let res = ft.send(
+        USERS[0],
+        FTAction::Burn {
+            transaction_id: None,
+            amount: 1000001,
+            reason: None,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::InsufficientBalance))));
+    // check that the balance of `USER[0]` is left untouched
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(1000000)))
+    )));
+    // must fail since `USERS[1]` doesn't have the burner role
+    let res = ft.send(
+        USERS[1],
+        FTAction::Burn {
+            transaction_id: None,
+            amount: 0,
+            reason: None,
         },
     );
-    assert!(res.main_failed());
+    assert!(res.contains(&(USERS[1], err(FTError::MissingRole(Role::Burner)))));
+}
+
+#[test]
+fn burn_from() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 1000,
+        },
+    );
+    assert!(!res.main_failed());
+
+    // must fail since `USERS[1]` didn't approve `USERS[0]` to spend tokens
+    let res = ft.send(
+        USERS[0],
+        FTAction::BurnFrom {
+            transaction_id: None,
+            from: USERS[1].into(),
+            amount: 400,
+            reason: Some(7),
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::InsufficientAllowance))));
+
+    let res = ft.send(
+        USERS[1],
+        FTAction::Approve {
+            transaction_id: None,
+            to: USERS[0].into(),
+            amount: 500,
+        },
+    );
+    assert!(!res.main_failed());
+
+    // must fail since `USERS[2]` doesn't have the burner role
+    let res = ft.send(
+        USERS[2],
+        FTAction::BurnFrom {
+            transaction_id: None,
+            from: USERS[1].into(),
+            amount: 400,
+            reason: Some(7),
+        },
+    );
+    assert!(res.contains(&(USERS[2], err(FTError::MissingRole(Role::Burner)))));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::BurnFrom {
+            transaction_id: None,
+            from: USERS[1].into(),
+            amount: 400,
+            reason: Some(7),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Burned {
+            from: USERS[1].into(),
+            amount: 400,
+            reason: Some(7),
+        })
+    )));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[1].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(600)))
+    )));
+    let res = ft.send(USERS[0], FTAction::Query(FTQuery::TotalSupply));
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::TotalSupply(999600)))
+    )));
+}
+
+#[test]
+fn transfer() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 500,
+        },
+    );
+
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Transfer {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 500,
+            fee: 0,
+        })
+    )));
+
+    // check that the balance of `USER[0]` decreased and the balance of `USER[1]` increased
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(999500)))
+    )));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[1].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(500)))
+    )));
+}
+
+#[test]
+fn transfer_failures() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    //must fail since the amount > balance
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 2000000,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::InsufficientBalance))));
+
+    //must fail transfer to zero address
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: 0.into(),
+            amount: 100,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::ZeroAddress))));
+
+    //must fail since `USERS[0]` didn't approve `USERS[1]` to spend tokens
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 1000,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Transfer {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 1000,
+            fee: 0,
+        })
+    )));
+    let res = ft.send(
+        USERS[1],
+        FTAction::TransferFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            to: USERS[2].into(),
+            amount: 100,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::InsufficientAllowance))));
+}
+
+#[test]
+fn batch_transfer() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    let res = ft.send(
+        USERS[0],
+        FTAction::BatchTransfer {
+            transaction_id: None,
+            transfers: vec![
+                (USERS[1].into(), 700),
+                (USERS[2].into(), 200),
+                (USERS[1].into(), 100),
+            ],
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::BatchTransfer {
+            from: USERS[0].into(),
+            transfers: vec![
+                (USERS[1].into(), 700, 0),
+                (USERS[2].into(), 200, 0),
+                (USERS[1].into(), 100, 0),
+            ],
+        })
+    )));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(999000)))
+    )));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[1].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(800)))
+    )));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[2].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(200)))
+    )));
+}
+
+#[test]
+fn batch_transfer_failures() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    // must fail since the total amount > balance
+    let res = ft.send(
+        USERS[0],
+        FTAction::BatchTransfer {
+            transaction_id: None,
+            transfers: vec![(USERS[1].into(), 500), (USERS[2].into(), 1000000)],
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::InsufficientBalance))));
+
+    // must fail since one of the recipients is the zero address
+    let res = ft.send(
+        USERS[0],
+        FTAction::BatchTransfer {
+            transaction_id: None,
+            transfers: vec![(USERS[1].into(), 500), (0.into(), 100)],
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::ZeroAddress))));
+
+    // check that no leg was applied
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(1000000)))
+    )));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[1].into())),
+    );
+    assert!(res.contains(&(USERS[0], ok(FTEvent::QueryReply(FTQueryReply::Balance(0))))));
+}
+
+#[test]
+fn batch_transfer_from() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    ft.send(
+        USERS[0],
+        FTAction::Approve {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 900,
+        },
+    );
+
+    // must fail since the total amount > allowance
+    let res = ft.send(
+        USERS[1],
+        FTAction::BatchTransferFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            transfers: vec![(USERS[2].into(), 600), (USERS[1].into(), 400)],
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::InsufficientAllowance))));
+
+    let res = ft.send(
+        USERS[1],
+        FTAction::BatchTransferFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            transfers: vec![(USERS[2].into(), 600), (USERS[1].into(), 300)],
+        },
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::BatchTransfer {
+            from: USERS[0].into(),
+            transfers: vec![(USERS[2].into(), 600, 0), (USERS[1].into(), 300, 0)],
+        })
+    )));
+    assert_balance(&ft, USERS[0], 999100);
+    assert_balance(&ft, USERS[1], 300);
+    assert_balance(&ft, USERS[2], 600);
+
+    // check that the whole allowance was spent
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::Allowance {
+            owner: USERS[0].into(),
+            spender: USERS[1].into(),
+        }),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Allowance {
+            owner: USERS[0].into(),
+            spender: USERS[1].into(),
+            amount: 0,
+        }))
+    )));
+}
+
+#[test]
+fn transfer_and_call_failures() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    // must fail transfer to zero address
+    let res = ft.send(
+        USERS[0],
+        FTAction::TransferAndCall {
+            transaction_id: None,
+            to: 0.into(),
+            amount: 100,
+            payload: vec![],
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::ZeroAddress))));
+
+    // must fail since the amount > balance
+    let res = ft.send(
+        USERS[1],
+        FTAction::TransferAndCall {
+            transaction_id: None,
+            to: USERS[2].into(),
+            amount: 100,
+            payload: vec![],
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::InsufficientBalance))));
+}
+
+/// Receiver of `TransferAndCall` replying with the given decision, or not replying at all.
+#[derive(Debug)]
+struct Receiver(Option<bool>);
+
+impl WasmProgram for Receiver {
+    fn init(&mut self, _payload: Vec<u8>) -> Result<Option<Vec<u8>>, &'static str> {
+        Ok(None)
+    }
+
+    fn handle(&mut self, _payload: Vec<u8>) -> Result<Option<Vec<u8>>, &'static str> {
+        Ok(self.0.map(|accepted| accepted.encode()))
+    }
+
+    fn handle_reply(&mut self, _payload: Vec<u8>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    fn handle_signal(&mut self, _payload: Vec<u8>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    fn state(&mut self) -> Result<Vec<u8>, &'static str> {
+        Ok(vec![])
+    }
+}
+
+fn init_receiver(sys: &System, decision: Option<bool>) {
+    let receiver = Program::mock_with_id(sys, RECEIVER, Receiver(decision));
+    receiver.send_bytes(USERS[0], b"");
+}
+
+fn assert_balance(ft: &Program, account: u64, balance: u128) {
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(account.into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(balance)))
+    )));
+}
+
+#[test]
+fn transfer_and_call() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    init_receiver(&sys, Some(true));
+    let ft = sys.get_program(1);
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::TransferAndCall {
+            transaction_id: None,
+            to: RECEIVER.into(),
+            amount: 1000,
+            payload: vec![],
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Transfer {
+            from: USERS[0].into(),
+            to: RECEIVER.into(),
+            amount: 1000,
+            fee: 0,
+        })
+    )));
+    assert_balance(&ft, USERS[0], 999000);
+    assert_balance(&ft, RECEIVER, 1000);
+}
+
+#[test]
+fn transfer_and_call_rejected() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    init_receiver(&sys, Some(false));
+    let ft = sys.get_program(1);
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::TransferAndCall {
+            transaction_id: Some(1),
+            to: RECEIVER.into(),
+            amount: 1000,
+            payload: vec![],
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::TransferRejected))));
+
+    // the escrowed tokens must be refunded
+    assert_balance(&ft, USERS[0], 1000000);
+    assert_balance(&ft, RECEIVER, 0);
+    assert_balance(&ft, 1, 0);
+}
+
+#[test]
+fn transfer_and_call_pending() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    init_receiver(&sys, None);
+    let ft = sys.get_program(1);
+
+    let transfer_and_call = || FTAction::TransferAndCall {
+        transaction_id: Some(1),
+        to: RECEIVER.into(),
+        amount: 1000,
+        payload: vec![],
+    };
+    // the receiver never replies, so the tokens stay escrowed
+    let res = ft.send(USERS[0], transfer_and_call());
+    assert!(res.log().is_empty());
+    assert_balance(&ft, USERS[0], 999000);
+
+    // must fail since the transaction is still waiting for the reply
+    let res = ft.send(USERS[0], transfer_and_call());
+    assert!(res.contains(&(USERS[0], err(FTError::TransactionPending))));
+    assert_balance(&ft, USERS[0], 999000);
+    assert_balance(&ft, 1, 1000);
+
+    // the tokens are returned once the reply times out
+    sys.spend_blocks(TRANSFER_AND_CALL_TIMEOUT + 1);
+    assert_balance(&ft, USERS[0], 1000000);
+    assert_balance(&ft, 1, 0);
+
+    // the timed out transaction can be retried with the same id
+    let res = ft.send(USERS[0], transfer_and_call());
+    assert!(res.log().is_empty());
+    assert_balance(&ft, USERS[0], 999000);
+    assert_balance(&ft, 1, 1000);
+}
+
+#[test]
+fn transfer_with_transaction_id() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    // the retried transaction must be replied with the cached event
+    for _ in 0..2 {
+        let res = ft.send(
+            USERS[0],
+            FTAction::Transfer {
+                transaction_id: Some(1),
+                to: USERS[1].into(),
+                amount: 500,
+            },
+        );
+        assert!(res.contains(&(
+            USERS[0],
+            ok(FTEvent::Transfer {
+                from: USERS[0].into(),
+                to: USERS[1].into(),
+                amount: 500,
+                fee: 0,
+            })
+        )));
+    }
+
+    // check that the transfer was executed only once
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(999500)))
+    )));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[1].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(500)))
+    )));
+
+    // the same transaction id of another account is a different transaction
+    let res = ft.send(
+        USERS[1],
+        FTAction::Transfer {
+            transaction_id: Some(1),
+            to: USERS[2].into(),
+            amount: 100,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::Transfer {
+            from: USERS[1].into(),
+            to: USERS[2].into(),
+            amount: 100,
+            fee: 0,
+        })
+    )));
+
+    // must fail since only the program itself can clear transactions
+    let res = ft.send(
+        USERS[0],
+        FTAction::Clear {
+            account: USERS[0].into(),
+            transaction_id: 1,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::NotAllowedToClear))));
+}
+
+#[test]
+fn pause_and_unpause() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    // must fail since `USERS[1]` doesn't have the pauser role
+    let res = ft.send(USERS[1], FTAction::Pause);
+    assert!(res.contains(&(USERS[1], err(FTError::MissingRole(Role::Pauser)))));
+
+    let res = ft.send(USERS[0], FTAction::Pause);
+    assert!(res.contains(&(USERS[0], ok(FTEvent::Paused))));
+
+    // must fail since the token is paused
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 500,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::Paused))));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 500,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::Paused))));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Burn {
+            transaction_id: None,
+            amount: 500,
+            reason: None,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::Paused))));
+
+    let res = ft.send(USERS[0], FTAction::Unpause);
+    assert!(res.contains(&(USERS[0], ok(FTEvent::Unpaused))));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 500,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Transfer {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 500,
+            fee: 0,
+        })
+    )));
+}
+
+#[test]
+fn freeze_and_unfreeze() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    // must fail since only the admin can freeze accounts
+    let res = ft.send(USERS[1], FTAction::Freeze(USERS[0].into()));
+    assert!(res.contains(&(USERS[1], err(FTError::NotAdmin))));
+
+    let res = ft.send(USERS[0], FTAction::Freeze(USERS[1].into()));
+    assert!(res.contains(&(USERS[0], ok(FTEvent::Frozen(USERS[1].into())))));
+
+    // must fail since the recipient is frozen
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 500,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::AccountFrozen(USERS[1].into())))));
+
+    let res = ft.send(USERS[0], FTAction::Unfreeze(USERS[1].into()));
+    assert!(res.contains(&(USERS[0], ok(FTEvent::Unfrozen(USERS[1].into())))));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 500,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Transfer {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 500,
+            fee: 0,
+        })
+    )));
+}
+
+#[test]
+fn frozen_spender() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Approve {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 1000,
+        },
+    );
+    assert!(!res.main_failed());
+    let res = ft.send(
+        USERS[0],
+        FTAction::GrantRole {
+            role: Role::Burner,
+            account: USERS[1].into(),
+        },
+    );
+    assert!(!res.main_failed());
+    let res = ft.send(USERS[0], FTAction::Freeze(USERS[1].into()));
+    assert!(res.contains(&(USERS[0], ok(FTEvent::Frozen(USERS[1].into())))));
+
+    // must fail since the spender is frozen
+    let res = ft.send(
+        USERS[1],
+        FTAction::TransferFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            to: USERS[2].into(),
+            amount: 500,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::AccountFrozen(USERS[1].into())))));
+
+    // must fail since the spender is frozen
+    let res = ft.send(
+        USERS[1],
+        FTAction::BatchTransferFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            transfers: vec![(USERS[2].into(), 500)],
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::AccountFrozen(USERS[1].into())))));
+
+    // must fail since the spender is frozen
+    let res = ft.send(
+        USERS[1],
+        FTAction::BurnFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            amount: 500,
+            reason: None,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::AccountFrozen(USERS[1].into())))));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::Allowance {
+            owner: USERS[0].into(),
+            spender: USERS[1].into(),
+        }),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Allowance {
+            owner: USERS[0].into(),
+            spender: USERS[1].into(),
+            amount: 1000,
+        }))
+    )));
+}
+
+fn permit_message(owner: ActorId, spender: u64, nonce: u64) -> PermitMessage {
+    PermitMessage {
+        owner,
+        spender: spender.into(),
+        amount: 500,
+        nonce,
+        deadline: u64::MAX,
+        program_id: 1.into(),
+    }
+}
+
+fn permit_action(signer: &Keypair, message: PermitMessage) -> FTAction {
+    let signature = signer.sign_simple(b"substrate", &message.encode());
+    FTAction::Permit {
+        owner: message.owner,
+        spender: message.spender,
+        amount: message.amount,
+        nonce: message.nonce,
+        deadline: message.deadline,
+        signature: signature.to_bytes(),
+    }
+}
+
+fn keypair(seed: u8) -> Keypair {
+    MiniSecretKey::from_bytes(&[seed; 32])
+        .expect("Unable to create a secret key")
+        .expand_to_keypair(ExpansionMode::Ed25519)
+}
+
+#[test]
+fn permit() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    let owner_keypair = keypair(7);
+    let owner = ActorId::new(owner_keypair.public.to_bytes());
+
+    // the permit is relayed by `USERS[2]` on behalf of the owner
+    let res = ft.send(
+        USERS[2],
+        permit_action(&owner_keypair, permit_message(owner, USERS[1], 0)),
+    );
+    assert!(res.contains(&(
+        USERS[2],
+        ok(FTEvent::Approve {
+            from: owner,
+            to: USERS[1].into(),
+            amount: 500,
+        })
+    )));
+    let res = ft.send(USERS[2], FTAction::Query(FTQuery::Nonce(owner)));
+    assert!(res.contains(&(USERS[2], ok(FTEvent::QueryReply(FTQueryReply::Nonce(1))))));
+
+    // must fail since the nonce was already used
+    let res = ft.send(
+        USERS[2],
+        permit_action(&owner_keypair, permit_message(owner, USERS[1], 0)),
+    );
+    assert!(res.contains(&(USERS[2], err(FTError::InvalidNonce))));
+
+    // must fail since the permit isn't signed by the owner
+    let res = ft.send(
+        USERS[2],
+        permit_action(&keypair(8), permit_message(owner, USERS[1], 1)),
+    );
+    assert!(res.contains(&(USERS[2], err(FTError::InvalidSignature))));
+
+    // must fail since the deadline has passed
+    let message = PermitMessage {
+        deadline: 0,
+        ..permit_message(owner, USERS[1], 1)
+    };
+    let res = ft.send(USERS[2], permit_action(&owner_keypair, message));
+    assert!(res.contains(&(USERS[2], err(FTError::PermitExpired))));
+
+    // must fail since the permit is signed for another program
+    let message = PermitMessage {
+        program_id: 2.into(),
+        ..permit_message(owner, USERS[1], 1)
+    };
+    let res = ft.send(USERS[2], permit_action(&owner_keypair, message));
+    assert!(res.contains(&(USERS[2], err(FTError::InvalidSignature))));
+
+    // the failed permits don't consume the nonce
+    let res = ft.send(
+        USERS[2],
+        permit_action(&owner_keypair, permit_message(owner, USERS[1], 1)),
+    );
+    assert!(res.contains(&(
+        USERS[2],
+        ok(FTEvent::Approve {
+            from: owner,
+            to: USERS[1].into(),
+            amount: 500,
+        })
+    )));
+}
+
+#[test]
+fn snapshot() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    // must fail since only the admin can take snapshots
+    let res = ft.send(USERS[1], FTAction::Snapshot);
+    assert!(res.contains(&(USERS[1], err(FTError::NotAdmin))));
+
+    let res = ft.send(USERS[0], FTAction::Snapshot);
+    assert!(res.contains(&(USERS[0], ok(FTEvent::Snapshot(1)))));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 500,
+        },
+    );
+    assert!(!res.main_failed());
+    let res = ft.send(
+        USERS[0],
+        FTAction::Burn {
+            transaction_id: None,
+            amount: 1000,
+            reason: None,
+        },
+    );
+    assert!(!res.main_failed());
+
+    let res = ft.send(USERS[0], FTAction::Snapshot);
+    assert!(res.contains(&(USERS[0], ok(FTEvent::Snapshot(2)))));
+
+    for (account, snapshot_id, balance) in [
+        (USERS[0], 1, 1000000),
+        (USERS[1], 1, 0),
+        (USERS[0], 2, 998500),
+        (USERS[1], 2, 500),
+    ] {
+        let res = ft.send(
+            USERS[2],
+            FTAction::Query(FTQuery::BalanceOfAt(account.into(), snapshot_id)),
+        );
+        assert!(res.contains(&(
+            USERS[2],
+            ok(FTEvent::QueryReply(FTQueryReply::Balance(balance)))
+        )));
+    }
+    let res = ft.send(USERS[2], FTAction::Query(FTQuery::TotalSupplyAt(1)));
+    assert!(res.contains(&(
+        USERS[2],
+        ok(FTEvent::QueryReply(FTQueryReply::TotalSupply(1000000)))
+    )));
+    let res = ft.send(USERS[2], FTAction::Query(FTQuery::TotalSupplyAt(2)));
+    assert!(res.contains(&(
+        USERS[2],
+        ok(FTEvent::QueryReply(FTQueryReply::TotalSupply(999000)))
+    )));
+
+    // must fail since the snapshot wasn't taken yet
+    let res = ft.send(USERS[2], FTAction::Query(FTQuery::TotalSupplyAt(3)));
+    assert!(res.contains(&(USERS[2], err(FTError::SnapshotNotFound))));
+
+    // the state functions must agree with the queries
+    for (account, snapshot_id, balance) in [
+        (USERS[0], 1, 1000000),
+        (USERS[1], 1, 0),
+        (USERS[0], 2, 998500),
+        (USERS[1], 2, 500),
+    ] {
+        let balance_at: Result<u128, FTError> =
+            read_state(&ft, "balance_of_at", (ActorId::from(account), snapshot_id));
+        assert_eq!(balance_at, Ok(balance));
+        let reply: Result<FTQueryReply, FTError> = read_state(
+            &ft,
+            "query",
+            FTQuery::BalanceOfAt(account.into(), snapshot_id),
+        );
+        assert!(matches!(reply, Ok(FTQueryReply::Balance(amount)) if amount == balance));
+    }
+    let total_supply_at: Result<u128, FTError> = read_state(&ft, "total_supply_at", 1u64);
+    assert_eq!(total_supply_at, Ok(1000000));
+    let total_supply_at: Result<u128, FTError> = read_state(&ft, "total_supply_at", 2u64);
+    assert_eq!(total_supply_at, Ok(999000));
+    let reply: Result<FTQueryReply, FTError> = read_state(&ft, "query", FTQuery::TotalSupplyAt(2));
+    assert!(matches!(reply, Ok(FTQueryReply::TotalSupply(999000))));
+    let reply: Result<FTQueryReply, FTError> = read_state(&ft, "query", FTQuery::TotalSupply);
+    assert!(matches!(reply, Ok(FTQueryReply::TotalSupply(999000))));
+
+    // must fail since there is no snapshot with these ids
+    for snapshot_id in [0u64, 3] {
+        let total_supply_at: Result<u128, FTError> =
+            read_state(&ft, "total_supply_at", snapshot_id);
+        assert_eq!(total_supply_at, Err(FTError::SnapshotNotFound));
+        let balance_at: Result<u128, FTError> =
+            read_state(&ft, "balance_of_at", (ActorId::from(USERS[0]), snapshot_id));
+        assert_eq!(balance_at, Err(FTError::SnapshotNotFound));
+        let reply: Result<FTQueryReply, FTError> =
+            read_state(&ft, "query", FTQuery::TotalSupplyAt(snapshot_id));
+        assert!(matches!(reply, Err(FTError::SnapshotNotFound)));
+    }
+}
+
+#[test]
+fn vesting() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::CreateVesting {
+            transaction_id: None,
+            beneficiary: USERS[1].into(),
+            total: 1000,
+            start: 0,
+            cliff: 0,
+            duration: 1,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::VestingCreated {
+            vesting_id: 0,
+            beneficiary: USERS[1].into(),
+            total: 1000,
+        })
+    )));
+
+    // check that the tokens are escrowed
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(999000)))
+    )));
+
+    sys.spend_blocks(10);
+    let res = ft.send(
+        USERS[1],
+        FTAction::Claim {
+            transaction_id: None,
+            vesting_id: 0,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::VestingClaimed {
+            vesting_id: 0,
+            beneficiary: USERS[1].into(),
+            amount: 1000,
+        })
+    )));
+    let res = ft.send(
+        USERS[1],
+        FTAction::Query(FTQuery::BalanceOf(USERS[1].into())),
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(1000)))
+    )));
+
+    // must fail since the fully released schedule is removed
+    let res = ft.send(
+        USERS[1],
+        FTAction::Claim {
+            transaction_id: None,
+            vesting_id: 0,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::VestingNotFound))));
+}
+
+#[test]
+fn vesting_with_cliff() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    let start = sys.block_timestamp();
+    sys.spend_blocks(1);
+    let block_duration = sys.block_timestamp() - start;
+    let (cliff, duration) = (10 * block_duration, 20 * block_duration);
+    let res = ft.send(
+        USERS[0],
+        FTAction::CreateVesting {
+            transaction_id: None,
+            beneficiary: USERS[1].into(),
+            total: 1000,
+            start,
+            cliff,
+            duration,
+        },
+    );
+    assert!(!res.main_failed());
+
+    // must fail since the cliff isn't reached yet
+    let res = ft.send(
+        USERS[1],
+        FTAction::Claim {
+            transaction_id: None,
+            vesting_id: 0,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::NothingToClaim))));
+
+    // only a part of the tokens is released between the cliff and the end
+    sys.spend_blocks(12);
+    let res = ft.send(
+        USERS[1],
+        FTAction::Claim {
+            transaction_id: None,
+            vesting_id: 0,
+        },
+    );
+    let reply = Result::<FTEvent, FTError>::decode(&mut res.log()[0].payload())
+        .expect("Unable to decode the reply");
+    let claimed = match reply {
+        Ok(FTEvent::VestingClaimed {
+            vesting_id: 0,
+            amount,
+            ..
+        }) => amount,
+        reply => panic!("Unexpected reply: {reply:?}"),
+    };
+    assert!((500..1000).contains(&claimed));
+
+    let vestings: Vec<(u64, VestingSchedule)> =
+        read_state(&ft, "vestings_of", ActorId::from(USERS[1]));
+    assert_eq!(vestings.len(), 1);
+    let (vesting_id, vesting) = &vestings[0];
+    assert_eq!(*vesting_id, 0);
+    assert_eq!(vesting.total, 1000);
+    assert_eq!(vesting.released, claimed);
+    assert_eq!((vesting.start, vesting.cliff), (start, cliff));
+    let vestings: Vec<(u64, VestingSchedule)> =
+        read_state(&ft, "vestings_of", ActorId::from(USERS[2]));
+    assert!(vestings.is_empty());
+
+    // the remainder is released at the end
+    sys.spend_blocks(10);
+    let res = ft.send(
+        USERS[1],
+        FTAction::Claim {
+            transaction_id: None,
+            vesting_id: 0,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::VestingClaimed {
+            vesting_id: 0,
+            beneficiary: USERS[1].into(),
+            amount: 1000 - claimed,
+        })
+    )));
+    let balance: u128 = read_state(&ft, "balances_of", ActorId::from(USERS[1]));
+    assert_eq!(balance, 1000);
+    let vestings: Vec<(u64, VestingSchedule)> =
+        read_state(&ft, "vestings_of", ActorId::from(USERS[1]));
+    assert!(vestings.is_empty());
+}
+
+#[test]
+fn vesting_failures() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    // must fail since the cliff is longer than the duration
+    let res = ft.send(
+        USERS[0],
+        FTAction::CreateVesting {
+            transaction_id: None,
+            beneficiary: USERS[1].into(),
+            total: 1000,
+            start: 0,
+            cliff: 10,
+            duration: 5,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::InvalidVestingSchedule))));
+
+    // must fail since the amount > balance
+    let res = ft.send(
+        USERS[1],
+        FTAction::CreateVesting {
+            transaction_id: None,
+            beneficiary: USERS[2].into(),
+            total: 1000,
+            start: 0,
+            cliff: 0,
+            duration: 5,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::InsufficientBalance))));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::CreateVesting {
+            transaction_id: None,
+            beneficiary: USERS[1].into(),
+            total: 1000,
+            start: u64::MAX / 2,
+            cliff: 0,
+            duration: 5,
+        },
+    );
+    assert!(!res.main_failed());
+
+    // must fail since the vesting hasn't started yet
+    let res = ft.send(
+        USERS[1],
+        FTAction::Claim {
+            transaction_id: None,
+            vesting_id: 0,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::NothingToClaim))));
+}
+
+#[test]
+fn approve_and_transfer() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Approve {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 500,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Approve {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 500,
+        })
+    )));
+
+    let res = ft.send(
+        USERS[1],
+        FTAction::TransferFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            to: USERS[2].into(),
+            amount: 200,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::Transfer {
+            from: USERS[0].into(),
+            to: USERS[2].into(),
+            amount: 200,
+            fee: 0,
+        })
+    )));
+
+    // check that the balance of `USER[0]` decreased and the balance of `USER[1]` increased
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[0].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(999800)))
+    )));
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(USERS[2].into())),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Balance(200)))
+    )));
+
+    // check that the allowance decreased
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::Allowance {
+            owner: USERS[0].into(),
+            spender: USERS[1].into(),
+        }),
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::Allowance {
+            owner: USERS[0].into(),
+            spender: USERS[1].into(),
+            amount: 300,
+        }))
+    )));
+
+    // must fail since not enough allowance
+    let res = ft.send(
+        USERS[1],
+        FTAction::TransferFrom {
+            transaction_id: None,
+            from: USERS[0].into(),
+            to: USERS[2].into(),
+            amount: 800,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::InsufficientAllowance))));
+}
+
+#[test]
+fn increase_and_decrease_allowance() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::IncreaseAllowance {
+            transaction_id: None,
+            spender: USERS[1].into(),
+            amount: 500,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Approve {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 500,
+        })
+    )));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::IncreaseAllowance {
+            transaction_id: None,
+            spender: USERS[1].into(),
+            amount: 300,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Approve {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 800,
+        })
+    )));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::DecreaseAllowance {
+            transaction_id: None,
+            spender: USERS[1].into(),
+            amount: 600,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Approve {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 200,
+        })
+    )));
+
+    // must fail since the allowance can't go below zero
+    let res = ft.send(
+        USERS[0],
+        FTAction::DecreaseAllowance {
+            transaction_id: None,
+            spender: USERS[1].into(),
+            amount: 201,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::AllowanceBelowZero))));
+}
+
+#[test]
+fn transfer_fee() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    let treasury: ActorId = 100.into();
+
+    // must fail since only the admin can set the fee
+    let res = ft.send(
+        USERS[1],
+        FTAction::UpdateFee {
+            fee_bps: 250,
+            treasury,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::NotAdmin))));
+
+    // must fail since the fee can't exceed 100%
+    let res = ft.send(
+        USERS[0],
+        FTAction::UpdateFee {
+            fee_bps: 10001,
+            treasury,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::InvalidFee))));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::UpdateFee {
+            fee_bps: 250,
+            treasury,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::FeeUpdated {
+            fee_bps: 250,
+            treasury,
+        })
+    )));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 1000,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Transfer {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 975,
+            fee: 25,
+        })
+    )));
+
+    let res = ft.send(USERS[0], FTAction::Query(FTQuery::BalanceOf(treasury)));
+    assert!(res.contains(&(USERS[0], ok(FTEvent::QueryReply(FTQueryReply::Balance(25))))));
+
+    let res = ft.send(
+        USERS[0],
+        FTAction::SetFeeExempt {
+            account: USERS[1].into(),
+            exempt: true,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::FeeExemptUpdated {
+            account: USERS[1].into(),
+            exempt: true,
+        })
+    )));
+
+    // transfers to an exempt account are free
+    let res = ft.send(
+        USERS[0],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 1000,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::Transfer {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            amount: 1000,
+            fee: 0,
+        })
+    )));
+
+    // every leg of a batch is charged on its own
+    let res = ft.send(
+        USERS[0],
+        FTAction::BatchTransfer {
+            transaction_id: None,
+            transfers: vec![(USERS[1].into(), 1000), (USERS[2].into(), 1000)],
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::BatchTransfer {
+            from: USERS[0].into(),
+            transfers: vec![(USERS[1].into(), 1000, 0), (USERS[2].into(), 975, 25)],
+        })
+    )));
+
+    let res = ft.send(USERS[0], FTAction::Query(FTQuery::BalanceOf(treasury)));
+    assert!(res.contains(&(USERS[0], ok(FTEvent::QueryReply(FTQueryReply::Balance(50))))));
+
+    let res = ft.send(USERS[0], FTAction::Query(FTQuery::TotalSupply));
+    assert!(res.contains(&(
+        USERS[0],
+        ok(FTEvent::QueryReply(FTQueryReply::TotalSupply(1000000)))
+    )));
+}
+
+/// A step of a randomized scenario, with users given by their indexes in `USERS`.
+#[derive(Debug, Clone)]
+enum Step {
+    Mint {
+        user: usize,
+        amount: u128,
+    },
+    Burn {
+        user: usize,
+        amount: u128,
+    },
+    Transfer {
+        from: usize,
+        to: usize,
+        amount: u128,
+    },
+    Approve {
+        owner: usize,
+        spender: usize,
+        amount: u128,
+    },
+    TransferFrom {
+        spender: usize,
+        from: usize,
+        to: usize,
+        amount: u128,
+    },
+    BurnFrom {
+        spender: usize,
+        from: usize,
+        amount: u128,
+    },
+}
+
+impl Step {
+    fn message(&self) -> (u64, FTAction) {
+        match *self {
+            Step::Mint { user, amount } => (
+                USERS[user],
+                FTAction::Mint {
+                    transaction_id: None,
+                    amount,
+                },
+            ),
+            Step::Burn { user, amount } => (
+                USERS[user],
+                FTAction::Burn {
+                    transaction_id: None,
+                    amount,
+                    reason: None,
+                },
+            ),
+            Step::Transfer { from, to, amount } => (
+                USERS[from],
+                FTAction::Transfer {
+                    transaction_id: None,
+                    to: USERS[to].into(),
+                    amount,
+                },
+            ),
+            Step::Approve {
+                owner,
+                spender,
+                amount,
+            } => (
+                USERS[owner],
+                FTAction::Approve {
+                    transaction_id: None,
+                    to: USERS[spender].into(),
+                    amount,
+                },
+            ),
+            Step::TransferFrom {
+                spender,
+                from,
+                to,
+                amount,
+            } => (
+                USERS[spender],
+                FTAction::TransferFrom {
+                    transaction_id: None,
+                    from: USERS[from].into(),
+                    to: USERS[to].into(),
+                    amount,
+                },
+            ),
+            Step::BurnFrom {
+                spender,
+                from,
+                amount,
+            } => (
+                USERS[spender],
+                FTAction::BurnFrom {
+                    transaction_id: None,
+                    from: USERS[from].into(),
+                    amount,
+                    reason: None,
+                },
+            ),
+        }
+    }
+}
+
+/// Deterministic xorshift generator, so a failing scenario is reproduced by every run.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn user(&mut self) -> usize {
+        self.below(USERS.len() as u64) as usize
+    }
+
+    fn amount(&mut self) -> u128 {
+        self.below(2000).into()
+    }
+
+    fn step(&mut self) -> Step {
+        match self.below(6) {
+            0 => Step::Mint {
+                user: self.user(),
+                amount: self.amount(),
+            },
+            1 => Step::Burn {
+                user: self.user(),
+                amount: self.amount(),
+            },
+            2 => Step::Transfer {
+                from: self.user(),
+                to: self.user(),
+                amount: self.amount(),
+            },
+            3 => Step::Approve {
+                owner: self.user(),
+                spender: self.user(),
+                amount: self.amount(),
+            },
+            4 => Step::TransferFrom {
+                spender: self.user(),
+                from: self.user(),
+                to: self.user(),
+                amount: self.amount(),
+            },
+            _ => Step::BurnFrom {
+                spender: self.user(),
+                from: self.user(),
+                amount: self.amount(),
+            },
+        }
+    }
+}
+
+fn query_reply(ft: &Program, query: FTQuery) -> FTQueryReply {
+    let res = ft.send(USERS[0], FTAction::Query(query));
+    let reply = Result::<FTEvent, FTError>::decode(&mut res.log()[0].payload())
+        .expect("Unable to decode the reply");
+    match reply {
+        Ok(FTEvent::QueryReply(reply)) => reply,
+        reply => panic!("Unexpected reply: {reply:?}"),
+    }
+}
+
+fn balances(ft: &Program) -> Vec<u128> {
+    USERS
+        .iter()
+        .map(
+            |user| match query_reply(ft, FTQuery::BalanceOf((*user).into())) {
+                FTQueryReply::Balance(balance) => balance,
+                reply => panic!("Unexpected reply: {reply:?}"),
+            },
+        )
+        .collect()
+}
+
+fn allowances(ft: &Program) -> Vec<Vec<u128>> {
+    USERS
+        .iter()
+        .map(|owner| {
+            USERS
+                .iter()
+                .map(|spender| {
+                    match query_reply(
+                        ft,
+                        FTQuery::Allowance {
+                            owner: (*owner).into(),
+                            spender: (*spender).into(),
+                        },
+                    ) {
+                        FTQueryReply::Allowance { amount, .. } => amount,
+                        reply => panic!("Unexpected reply: {reply:?}"),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn total_supply(ft: &Program) -> u128 {
+    match query_reply(ft, FTQuery::TotalSupply) {
+        FTQueryReply::TotalSupply(total_supply) => total_supply,
+        reply => panic!("Unexpected reply: {reply:?}"),
+    }
+}
+
+fn ensure(condition: bool, violation: &str) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(violation.into())
+    }
+}
+
+/// Runs `steps` on a new program, returning the first broken invariant.
+fn check_invariants(steps: &[Step]) -> Result<(), String> {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    for user in &USERS[1..] {
+        for role in [Role::Minter, Role::Burner] {
+            ft.send(
+                USERS[0],
+                FTAction::GrantRole {
+                    role,
+                    account: (*user).into(),
+                },
+            );
+        }
+    }
+
+    // The expected state, updated only by the steps the program accepted.
+    let mut expected_balances = balances(&ft);
+    let mut expected_allowances = allowances(&ft);
+    for step in steps {
+        let (sender, action) = step.message();
+        let res = ft.send(sender, action);
+        let accepted = Result::<FTEvent, FTError>::decode(&mut res.log()[0].payload())
+            .expect("Unable to decode the reply")
+            .is_ok();
+
+        if accepted {
+            match *step {
+                Step::Mint { user, amount } => expected_balances[user] += amount,
+                Step::Burn { user, amount } => {
+                    ensure(expected_balances[user] >= amount, "negative balance")?;
+                    expected_balances[user] -= amount;
+                }
+                Step::Transfer { from, to, amount } => {
+                    ensure(expected_balances[from] >= amount, "negative balance")?;
+                    expected_balances[from] -= amount;
+                    expected_balances[to] += amount;
+                }
+                Step::Approve {
+                    owner,
+                    spender,
+                    amount,
+                } => {
+                    expected_allowances[owner][spender] = amount;
+                }
+                Step::TransferFrom {
+                    spender,
+                    from,
+                    to,
+                    amount,
+                } => {
+                    ensure(
+                        expected_allowances[from][spender] >= amount,
+                        "negative allowance",
+                    )?;
+                    ensure(expected_balances[from] >= amount, "negative balance")?;
+                    expected_allowances[from][spender] -= amount;
+                    expected_balances[from] -= amount;
+                    expected_balances[to] += amount;
+                }
+                Step::BurnFrom {
+                    spender,
+                    from,
+                    amount,
+                } => {
+                    ensure(
+                        expected_allowances[from][spender] >= amount,
+                        "negative allowance",
+                    )?;
+                    ensure(expected_balances[from] >= amount, "negative balance")?;
+                    expected_allowances[from][spender] -= amount;
+                    expected_balances[from] -= amount;
+                }
+            }
+        }
+
+        let balances = balances(&ft);
+        ensure(balances == expected_balances, "unexpected balances")?;
+        ensure(
+            balances.iter().sum::<u128>() == total_supply(&ft),
+            "balances don't add up to the total supply",
+        )?;
+        // Allowances only change through approvals and decrease only through spending.
+        ensure(
+            allowances(&ft) == expected_allowances,
+            "unexpected allowances",
+        )?;
+    }
+    Ok(())
+}
+
+/// Drops the steps that aren't needed to break an invariant, one at a time.
+fn shrink(mut steps: Vec<Step>) -> Vec<Step> {
+    let mut index = 0;
+    while index < steps.len() {
+        let mut candidate = steps.clone();
+        candidate.remove(index);
+        if check_invariants(&candidate).is_err() {
+            steps = candidate;
+        } else {
+            index += 1;
+        }
+    }
+    steps
+}
+
+#[test]
+fn invariants() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    // Every case deploys a new program, so keep their number modest.
+    for _ in 0..32 {
+        let len = 1 + rng.below(23);
+        let steps: Vec<_> = (0..len).map(|_| rng.step()).collect();
+        if let Err(violation) = check_invariants(&steps) {
+            let steps = shrink(steps);
+            panic!("{violation} after {steps:?}");
+        }
+    }
+}
+
+#[test]
+fn deposit_and_withdraw() {
+    let sys = System::new();
+    sys.init_logger();
+    let ft = Program::current(&sys);
+    let res = ft.send(
+        USERS[0],
+        InitConfig {
+            name: String::from("Wrapped value"),
+            symbol: String::from("WVAL"),
+            decimals: 12,
+            icon: String::new(),
+            description: String::new(),
+            website: String::new(),
+            max_supply: None,
+            admin: USERS[0].into(),
+            fee_bps: 0,
+            treasury: 0.into(),
+            fee_exempt: vec![],
+            wrapped_native: true,
+        },
+    );
+    assert!(res.log().is_empty());
+    sys.mint_to(USERS[1], 10000);
+
+    let res = ft.send_with_value(USERS[1], FTAction::Deposit, 4000);
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::Deposited {
+            account: USERS[1].into(),
+            amount: 4000,
+        })
+    )));
+    assert_eq!(sys.balance_of(ft.id()), 4000);
+
+    // must fail since the supply of a wrapped token changes only by deposits
+    let res = ft.send(
+        USERS[0],
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 1000,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(FTError::WrappedNative))));
+
+    // must fail since the balance is lower than the withdrawn amount
+    let res = ft.send(
+        USERS[1],
+        FTAction::Withdraw {
+            transaction_id: None,
+            amount: 4001,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(FTError::InsufficientBalance))));
+
+    // the retried withdrawal must send the value only once
+    for _ in 0..2 {
+        let res = ft.send(
+            USERS[1],
+            FTAction::Withdraw {
+                transaction_id: Some(1),
+                amount: 1500,
+            },
+        );
+        assert!(res.contains(&(
+            USERS[1],
+            ok(FTEvent::Withdrawn {
+                account: USERS[1].into(),
+                amount: 1500,
+            })
+        )));
+        assert_eq!(sys.balance_of(ft.id()), 2500);
+    }
+
+    let res = ft.send(USERS[1], FTAction::Query(FTQuery::TotalSupply));
+    assert!(res.contains(&(
+        USERS[1],
+        ok(FTEvent::QueryReply(FTQueryReply::TotalSupply(2500)))
+    )));
+}
+
+#[test]
+fn deposit_failures() {
+    let sys = System::new();
+    init_with_mint(&sys);
+    let ft = sys.get_program(1);
+    sys.mint_to(USERS[1], 10000);
+
+    // must fail since the token doesn't wrap the native value
+    let res = ft.send_with_value(USERS[1], FTAction::Deposit, 4000);
+    assert!(res.contains(&(USERS[1], err(FTError::NotWrappedNative))));
+    // the value is returned with the reply
+    assert_eq!(sys.balance_of(ft.id()), 0);
 }
@@ -4,6 +4,9 @@ use ft_io::*;
 use gmeta::{metawasm, Metadata};
 use gstd::{prelude::*, ActorId};
 
+#[cfg(feature = "binary-vendor")]
+include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
+
 #[metawasm]
 pub mod metafns {
     pub type State = <FungibleTokenMetadata as Metadata>::State;
@@ -16,6 +19,18 @@ pub mod metafns {
         state.symbol
     }
 
+    pub fn icon(state: State) -> String {
+        state.icon
+    }
+
+    pub fn description(state: State) -> String {
+        state.description
+    }
+
+    pub fn website(state: State) -> String {
+        state.website
+    }
+
     pub fn decimals(state: State) -> u8 {
         state.decimals
     }
@@ -24,10 +39,170 @@ pub mod metafns {
         state.total_supply
     }
 
+    pub fn max_supply(state: State) -> Option<u128> {
+        state.max_supply
+    }
+
+    pub fn transfer_fee(state: State) -> (u16, ActorId) {
+        (state.fee_bps, state.treasury)
+    }
+
+    pub fn is_fee_exempt(state: State, account: ActorId) -> bool {
+        state.fee_exempt.binary_search(&account).is_ok()
+    }
+
+    pub fn wrapped_native(state: State) -> bool {
+        state.wrapped_native
+    }
+
     pub fn balances_of(state: State, account: ActorId) -> u128 {
-        match state.balances.iter().find(|(id, _balance)| account.eq(id)) {
-            Some((_id, balance)) => *balance,
-            None => panic!("Balance for account ID {account:?} not found",),
-        }
+        balance_helper(&state, &account)
+    }
+
+    pub fn allowance(state: State, owner: ActorId, spender: ActorId) -> u128 {
+        allowance_helper(&state, &owner, &spender)
+    }
+
+    pub fn query(state: State, query: FTQuery) -> Result<FTQueryReply, FTError> {
+        let reply = match query {
+            FTQuery::Name => FTQueryReply::Name(state.name),
+            FTQuery::Symbol => FTQueryReply::Symbol(state.symbol),
+            FTQuery::Decimals => FTQueryReply::Decimals(state.decimals),
+            FTQuery::TotalSupply => FTQueryReply::TotalSupply(state.total_supply),
+            FTQuery::BalanceOf(account) => FTQueryReply::Balance(balance_helper(&state, &account)),
+            FTQuery::Allowance { owner, spender } => FTQueryReply::Allowance {
+                owner,
+                spender,
+                amount: allowance_helper(&state, &owner, &spender),
+            },
+            FTQuery::Nonce(owner) => FTQueryReply::Nonce(
+                state
+                    .nonces
+                    .iter()
+                    .find(|(id, _nonce)| owner.eq(id))
+                    .map(|(_id, nonce)| *nonce)
+                    .unwrap_or_default(),
+            ),
+            FTQuery::BalanceOfAt(account, snapshot_id) => {
+                FTQueryReply::Balance(balance_at_helper(&state, &account, snapshot_id)?)
+            }
+            FTQuery::Holders { offset, limit } => {
+                FTQueryReply::Holders(page(state.balances, offset, limit))
+            }
+            FTQuery::Allowances {
+                owner,
+                offset,
+                limit,
+            } => FTQueryReply::Allowances(
+                state
+                    .allowances
+                    .into_iter()
+                    .find(|(id, _allowance)| *id == owner)
+                    .map(|(_id, allowance)| page(allowance, offset, limit))
+                    .unwrap_or_default(),
+            ),
+            FTQuery::TotalSupplyAt(snapshot_id) => {
+                FTQueryReply::TotalSupply(total_supply_at_helper(&state, snapshot_id)?)
+            }
+        };
+        Ok(reply)
+    }
+
+    pub fn balance_of_at(
+        state: State,
+        account: ActorId,
+        snapshot_id: u64,
+    ) -> Result<u128, FTError> {
+        balance_at_helper(&state, &account, snapshot_id)
+    }
+
+    pub fn total_supply_at(state: State, snapshot_id: u64) -> Result<u128, FTError> {
+        total_supply_at_helper(&state, snapshot_id)
+    }
+
+    pub fn vestings_of(state: State, beneficiary: ActorId) -> Vec<(u64, VestingSchedule)> {
+        state
+            .vestings
+            .into_iter()
+            .filter(|(_id, vesting)| vesting.beneficiary == beneficiary)
+            .collect()
+    }
+
+    pub fn holders(state: State, offset: u64, limit: u64) -> Vec<(ActorId, u128)> {
+        page(state.balances, offset, limit)
+    }
+
+    pub fn top_holders(state: State, n: u64) -> Vec<(ActorId, u128)> {
+        let mut holders = state.balances;
+        holders.sort_by(|(_, a), (_, b)| b.cmp(a));
+        holders.truncate(n as usize);
+        holders
+    }
+}
+
+fn page(entries: Vec<(ActorId, u128)>, offset: u64, limit: u64) -> Vec<(ActorId, u128)> {
+    entries
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect()
+}
+
+fn balance_helper(state: &<FungibleTokenMetadata as Metadata>::State, account: &ActorId) -> u128 {
+    state
+        .balances
+        .binary_search_by_key(account, |(id, _balance)| *id)
+        .map(|index| state.balances[index].1)
+        .unwrap_or_default()
+}
+
+fn allowance_helper(
+    state: &<FungibleTokenMetadata as Metadata>::State,
+    owner: &ActorId,
+    spender: &ActorId,
+) -> u128 {
+    state
+        .allowances
+        .binary_search_by_key(owner, |(id, _allowance)| *id)
+        .ok()
+        .and_then(|index| {
+            let (_owner, allowance) = &state.allowances[index];
+            allowance
+                .binary_search_by_key(spender, |(id, _amount)| *id)
+                .ok()
+                .map(|index| allowance[index].1)
+        })
+        .unwrap_or_default()
+}
+
+fn balance_at_helper(
+    state: &<FungibleTokenMetadata as Metadata>::State,
+    account: &ActorId,
+    snapshot_id: u64,
+) -> Result<u128, FTError> {
+    check_snapshot_id(state, snapshot_id)?;
+    let checkpoint = state
+        .account_snapshots
+        .binary_search_by_key(account, |(id, _checkpoints)| *id)
+        .ok()
+        .and_then(|index| checkpoint_at(&state.account_snapshots[index].1, snapshot_id));
+    Ok(checkpoint.unwrap_or_else(|| balance_helper(state, account)))
+}
+
+fn total_supply_at_helper(
+    state: &<FungibleTokenMetadata as Metadata>::State,
+    snapshot_id: u64,
+) -> Result<u128, FTError> {
+    check_snapshot_id(state, snapshot_id)?;
+    Ok(checkpoint_at(&state.total_supply_snapshots, snapshot_id).unwrap_or(state.total_supply))
+}
+
+fn check_snapshot_id(
+    state: &<FungibleTokenMetadata as Metadata>::State,
+    snapshot_id: u64,
+) -> Result<(), FTError> {
+    if snapshot_id == 0 || snapshot_id > state.snapshot_id {
+        return Err(FTError::SnapshotNotFound);
     }
+    Ok(())
 }
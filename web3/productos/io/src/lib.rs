@@ -12,11 +12,14 @@ use gstd::{prelude::*, ActorId};
 pub use gear_lib::non_fungible_token::delegated::DelegatedApproveMessage;
 use primitive_types::H256;
 
+/// Blocks a purchase waits for the currency to reply before it's left pending.
+pub const PAYMENT_TIMEOUT: u32 = 100;
+
 pub struct NFTMetadata;
 
 impl Metadata for NFTMetadata {
     type Init = In<InitNFT>;
-    type Handle = InOut<NFTAction, NFTEvent>;
+    type Handle = InOut<NFTAction, Result<NFTEvent, NFTError>>;
     type Reply = ();
     type Others = ();
     type Signal = ();
@@ -67,6 +70,37 @@ pub enum NFTAction {
         to: ActorId,
         token_id: TokenId,
     },
+    List {
+        token_id: TokenId,
+        price: u128,
+        currency: ActorId,
+        quantity: u32,
+    },
+    Delist {
+        token_id: TokenId,
+    },
+    UpdatePrice {
+        token_id: TokenId,
+        price: u128,
+    },
+    /// Buys a listed token paying its price with the listing currency,
+    /// which the buyer must have approved the program to spend.
+    Buy {
+        token_id: TokenId,
+    },
+    /// Retries the payment of a pending purchase, completing or rolling it back.
+    ResolvePurchase {
+        token_id: TokenId,
+    },
+    AddMinter {
+        minter: ActorId,
+    },
+    RemoveMinter {
+        minter: ActorId,
+    },
+    TransferOwnership {
+        new_owner: ActorId,
+    },
     Clear {
         transaction_hash: H256,
     },
@@ -80,6 +114,10 @@ pub struct InitNFT {
     pub symbol: String,
     pub base_uri: String,
     pub royalties: Option<Royalties>,
+    /// Blocks after which a cached transaction is cleared, or 0 to keep it until evicted.
+    pub transaction_ttl: u32,
+    /// Cached transactions kept per account, the oldest being evicted first.
+    pub max_transactions: u32,
 }
 
 #[derive(Encode, Decode, TypeInfo, Debug, Clone)]
@@ -99,6 +137,80 @@ pub enum NFTEvent {
         token_id: TokenId,
         approved: bool,
     },
+    Listed {
+        token_id: TokenId,
+        listing: Listing,
+    },
+    Delisted {
+        token_id: TokenId,
+    },
+    PriceUpdated {
+        token_id: TokenId,
+        price: u128,
+    },
+    Sold {
+        token_id: TokenId,
+        seller: ActorId,
+        buyer: ActorId,
+        price: u128,
+    },
+    MinterAdded {
+        minter: ActorId,
+    },
+    MinterRemoved {
+        minter: ActorId,
+    },
+    OwnershipTransferred {
+        previous_owner: ActorId,
+        new_owner: ActorId,
+    },
+    TransactionCleared {
+        transaction_hash: H256,
+    },
+}
+
+/// Sale terms of a product.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct Listing {
+    /// Price in the smallest units of `currency`.
+    pub price: u128,
+    /// Fungible token program the price is paid in.
+    pub currency: ActorId,
+    /// Always 1, as every token is unique.
+    pub quantity: u32,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub enum NFTError {
+    ZeroAddress,
+    TokenNotFound,
+    NotTokenOwner,
+    /// The sender neither owns the token nor is approved for it.
+    NotApproved,
+    NotCollectionOwner,
+    NotMinter,
+    InvalidQuantity,
+    NotListed,
+    NotForSale,
+    SelfPurchase,
+    /// The payment failed and the purchase was rolled back.
+    PaymentFailed,
+    /// The currency didn't reply in time, so the token stays escrowed
+    /// until the purchase is resolved.
+    PaymentPending,
+    NoPendingPurchase,
+    /// The sender isn't the actor approved by the delegated approval.
+    WrongApprovedActor,
+    /// The delegated approval was signed for another program.
+    WrongProgram,
+    ApprovalExpired,
+    InvalidSignature,
+    NotAllowedToClear,
 }
 
 #[derive(Debug, Clone, Default, Encode, Decode, TypeInfo)]
@@ -115,6 +227,29 @@ pub struct IoNFTState {
     pub royalties: Option<Royalties>,
 }
 
+/// Result of a processed transaction, replayed when it's retried.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct CachedTransaction {
+    pub account: ActorId,
+    /// Block height the transaction was processed at.
+    pub block_height: u32,
+    pub result: Result<NFTEvent, NFTError>,
+}
+
+/// Purchase of an escrowed token whose payment wasn't confirmed by the currency.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct PendingPurchase {
+    pub buyer: ActorId,
+    pub seller: ActorId,
+    pub listing: Listing,
+    /// Id of the currency transaction paying for the token, reused by the retries.
+    pub transaction_id: u64,
+}
+
 #[derive(Debug, Clone, Default, Encode, Decode, TypeInfo)]
 #[codec(crate = gstd::codec)]
 #[scale_info(crate = gstd::scale_info)]
@@ -122,7 +257,13 @@ pub struct IoNFT {
     pub token: IoNFTState,
     pub token_id: TokenId,
     pub owner: ActorId,
-    pub transactions: Vec<(H256, NFTEvent)>,
+    pub transactions: Vec<(H256, CachedTransaction)>,
+    pub transaction_ttl: u32,
+    pub max_transactions: u32,
+    pub minters: Vec<ActorId>,
+    /// Listings sorted by the token id.
+    pub listings: Vec<(TokenId, Listing)>,
+    pub pending_purchases: Vec<(TokenId, PendingPurchase)>,
 }
 
 impl From<&NFTState> for IoNFTState {
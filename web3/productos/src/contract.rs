@@ -1,18 +1,40 @@
+use ft_io::{FTAction, FTError, FTEvent};
 use gear_lib::non_fungible_token::{io::NFTTransfer, nft_core::*, state::*, token::*};
 use gear_lib_derive::{NFTCore, NFTMetaState, NFTStateKeeper};
 use gmeta::Metadata;
 use gstd::{errors::Result as GstdResult, exec, msg, prelude::*, ActorId, MessageId};
-use hashbrown::HashMap;
-use nft_io::{InitNFT, IoNFT, NFTAction, NFTEvent, NFTMetadata};
+use hashbrown::{HashMap, HashSet};
+use nft_io::{
+    CachedTransaction, DelegatedApproveMessage, InitNFT, IoNFT, Listing, NFTAction, NFTError,
+    NFTEvent, NFTMetadata, PendingPurchase, PAYMENT_TIMEOUT,
+};
 use primitive_types::{H256, U256};
 
+const ZERO_ID: ActorId = ActorId::new([0u8; 32]);
+/// Signing context used by the substrate sr25519 signatures.
+const SIGNING_CONTEXT: &[u8] = b"substrate";
+
 #[derive(Debug, Default, NFTStateKeeper, NFTCore, NFTMetaState)]
 pub struct Nft {
     #[NFTStateField]
     pub token: NFTState,
     pub token_id: TokenId,
     pub owner: ActorId,
-    pub transactions: HashMap<H256, NFTEvent>,
+    pub transactions: HashMap<H256, CachedTransaction>,
+    /// Hashes of the cached transactions of each account, the oldest first.
+    pub transactions_by_account: HashMap<ActorId, Vec<H256>>,
+    /// Blocks after which a cached transaction is cleared, or 0 to keep it until evicted.
+    pub transaction_ttl: u32,
+    /// Cached transactions kept per account.
+    pub max_transactions: u32,
+    /// Accounts allowed to mint besides the collection owner.
+    pub minters: HashSet<ActorId>,
+    /// Sale terms of the listed products.
+    pub listings: HashMap<TokenId, Listing>,
+    /// Purchases of the escrowed tokens waiting for their payment to be resolved.
+    pub pending_purchases: HashMap<TokenId, PendingPurchase>,
+    /// Id of the currency transaction paying for the next purchase.
+    pub purchase_id: u64,
 }
 
 static mut CONTRACT: Option<Nft> = None;
@@ -23,6 +45,10 @@ unsafe extern "C" fn init() {
     if config.royalties.is_some() {
         config.royalties.as_ref().expect("Unable to g").validate();
     }
+    assert!(
+        config.max_transactions > 0,
+        "At least one transaction must be cached per account"
+    );
     let nft = Nft {
         token: NFTState {
             name: config.name,
@@ -32,134 +58,119 @@ unsafe extern "C" fn init() {
             ..Default::default()
         },
         owner: msg::source(),
+        transaction_ttl: config.transaction_ttl,
+        max_transactions: config.max_transactions,
         ..Default::default()
     };
     CONTRACT = Some(nft);
 }
 
-#[no_mangle]
-unsafe extern "C" fn handle() {
+#[gstd::async_main]
+async fn main() {
     let action: NFTAction = msg::load().expect("Could not load NFTAction");
-    let nft = CONTRACT.get_or_insert(Default::default());
-    match action {
+    let nft = unsafe { CONTRACT.get_or_insert(Default::default()) };
+    let result = match action {
         NFTAction::Mint {
             transaction_id,
             token_metadata,
-        } => {
-            msg::reply(
-                nft.process_transaction(transaction_id, |nft| {
-                    NFTEvent::Transfer(MyNFTCore::mint(nft, token_metadata))
-                }),
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::Transfer`");
-        }
+        } => nft.process_transaction(transaction_id, |nft| {
+            MyNFTCore::mint(nft, token_metadata).map(NFTEvent::Transfer)
+        }),
         NFTAction::Burn {
             transaction_id,
             token_id,
-        } => {
-            msg::reply(
-                nft.process_transaction(transaction_id, |nft| {
-                    NFTEvent::Transfer(NFTCore::burn(nft, token_id))
-                }),
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::Transfer`");
-        }
+        } => nft.process_transaction(transaction_id, |nft| {
+            nft.check_token_owner(token_id)?;
+            nft.listings.remove(&token_id);
+            Ok(NFTEvent::Transfer(NFTCore::burn(nft, token_id)))
+        }),
         NFTAction::Transfer {
             transaction_id,
             to,
             token_id,
-        } => {
-            msg::reply(
-                nft.process_transaction(transaction_id, |nft| {
-                    NFTEvent::Transfer(NFTCore::transfer(nft, &to, token_id))
-                }),
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::Transfer`");
-        }
+        } => nft.process_transaction(transaction_id, |nft| {
+            nft.check_can_transfer(&to, token_id)?;
+            nft.listings.remove(&token_id);
+            Ok(NFTEvent::Transfer(NFTCore::transfer(nft, &to, token_id)))
+        }),
         NFTAction::TransferPayout {
             transaction_id,
             to,
             token_id,
             amount,
-        } => {
-            msg::reply(
-                nft.process_transaction(transaction_id, |nft| {
-                    NFTEvent::TransferPayout(NFTCore::transfer_payout(nft, &to, token_id, amount))
-                }),
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::TransferPayout`");
-        }
-        NFTAction::NFTPayout { owner, amount } => {
-            msg::reply(
-                NFTEvent::NFTPayout(NFTCore::nft_payout(nft, &owner, amount)),
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::NFTPayout`");
-        }
+        } => nft.process_transaction(transaction_id, |nft| {
+            nft.check_can_transfer(&to, token_id)?;
+            nft.listings.remove(&token_id);
+            Ok(NFTEvent::TransferPayout(NFTCore::transfer_payout(
+                nft, &to, token_id, amount,
+            )))
+        }),
+        NFTAction::NFTPayout { owner, amount } => Ok(NFTEvent::NFTPayout(NFTCore::nft_payout(
+            nft, &owner, amount,
+        ))),
         NFTAction::Approve {
             transaction_id,
             to,
             token_id,
-        } => {
-            msg::reply(
-                nft.process_transaction(transaction_id, |nft| {
-                    NFTEvent::Approval(NFTCore::approve(nft, &to, token_id))
-                }),
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::Approval`");
-        }
-        NFTAction::Owner { token_id } => {
-            msg::reply(
-                NFTEvent::Owner {
-                    owner: NFTCore::owner_of(nft, token_id),
-                    token_id,
-                },
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::Owner`");
-        }
+        } => nft.process_transaction(transaction_id, |nft| {
+            if to == ZERO_ID {
+                return Err(NFTError::ZeroAddress);
+            }
+            nft.check_token_owner(token_id)?;
+            Ok(NFTEvent::Approval(NFTCore::approve(nft, &to, token_id)))
+        }),
+        NFTAction::Owner { token_id } => nft
+            .token_owner(token_id)
+            .map(|owner| NFTEvent::Owner { owner, token_id }),
         NFTAction::IsApproved { to, token_id } => {
-            msg::reply(
-                NFTEvent::IsApproved {
+            nft.token_owner(token_id)
+                .map(|_owner| NFTEvent::IsApproved {
                     to,
                     token_id,
-                    approved: NFTCore::is_approved_to(nft, &to, token_id),
-                },
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::IsApproved`");
+                    approved: nft.is_approved(&to, token_id),
+                })
         }
         NFTAction::DelegatedApprove {
             transaction_id,
             message,
             signature,
-        } => {
-            msg::reply(
-                nft.process_transaction(transaction_id, |nft| {
-                    NFTEvent::Approval(NFTCore::delegated_approve(nft, message, signature))
-                }),
-                0,
-            )
-            .expect("Error during replying with `NFTEvent::Approval`");
-        }
+        } => nft.process_transaction(transaction_id, |nft| {
+            nft.check_delegated_approve(&message, &signature)?;
+            Ok(NFTEvent::Approval(NFTCore::delegated_approve(
+                nft, message, signature,
+            )))
+        }),
+        NFTAction::List {
+            token_id,
+            price,
+            currency,
+            quantity,
+        } => nft.list(token_id, price, currency, quantity),
+        NFTAction::Delist { token_id } => nft.delist(token_id),
+        NFTAction::UpdatePrice { token_id, price } => nft.update_price(token_id, price),
+        NFTAction::Buy { token_id } => nft.buy(token_id).await,
+        NFTAction::ResolvePurchase { token_id } => nft.resolve_purchase(token_id).await,
+        NFTAction::AddMinter { minter } => nft.add_minter(minter),
+        NFTAction::RemoveMinter { minter } => nft.remove_minter(minter),
+        NFTAction::TransferOwnership { new_owner } => nft.transfer_ownership(new_owner),
         NFTAction::Clear { transaction_hash } => nft.clear(transaction_hash),
     };
+    reply(result).expect("Failed to encode or reply with `Result<NFTEvent, NFTError>`");
 }
 
 pub trait MyNFTCore: NFTCore {
-    fn mint(&mut self, token_metadata: TokenMetadata) -> NFTTransfer;
+    fn mint(&mut self, token_metadata: TokenMetadata) -> Result<NFTTransfer, NFTError>;
 }
 
 impl MyNFTCore for Nft {
-    fn mint(&mut self, token_metadata: TokenMetadata) -> NFTTransfer {
-        let transfer = NFTCore::mint(self, &msg::source(), self.token_id, Some(token_metadata));
+    fn mint(&mut self, token_metadata: TokenMetadata) -> Result<NFTTransfer, NFTError> {
+        let source = msg::source();
+        if source != self.owner && !self.minters.contains(&source) {
+            return Err(NFTError::NotMinter);
+        }
+        let transfer = NFTCore::mint(self, &source, self.token_id, Some(token_metadata));
         self.token_id = self.token_id.saturating_add(U256::one());
-        transfer
+        Ok(transfer)
     }
 }
 
@@ -167,29 +178,317 @@ impl Nft {
     fn process_transaction(
         &mut self,
         transaction_id: u64,
-        action: impl FnOnce(&mut Nft) -> NFTEvent,
-    ) -> NFTEvent {
-        let transaction_hash = get_hash(&msg::source(), transaction_id);
+        action: impl FnOnce(&mut Nft) -> Result<NFTEvent, NFTError>,
+    ) -> Result<NFTEvent, NFTError> {
+        let account = msg::source();
+        let transaction_hash = get_hash(&account, transaction_id);
 
-        if let Some(nft_event) = self.transactions.get(&transaction_hash) {
-            nft_event.clone()
+        if let Some(transaction) = self.transactions.get(&transaction_hash) {
+            transaction.result.clone()
         } else {
-            let nft_event = action(self);
+            let result = action(self);
 
-            self.transactions
-                .insert(transaction_hash, nft_event.clone());
+            self.cache_transaction(account, transaction_hash, result.clone());
 
-            nft_event
+            result
         }
     }
 
-    fn clear(&mut self, transaction_hash: H256) {
-        assert_eq!(
-            msg::source(),
-            exec::program_id(),
-            "Not allowed to clear transactions"
+    /// Caches `result` evicting the oldest transaction of `account` over the limit,
+    /// and schedules clearing it once it expires.
+    fn cache_transaction(
+        &mut self,
+        account: ActorId,
+        transaction_hash: H256,
+        result: Result<NFTEvent, NFTError>,
+    ) {
+        let hashes = self.transactions_by_account.entry(account).or_default();
+        hashes.push(transaction_hash);
+        if hashes.len() > self.max_transactions as usize {
+            let oldest = hashes.remove(0);
+            self.transactions.remove(&oldest);
+        }
+        self.transactions.insert(
+            transaction_hash,
+            CachedTransaction {
+                account,
+                block_height: exec::block_height(),
+                result,
+            },
         );
+
+        if self.transaction_ttl > 0 {
+            msg::send_delayed(
+                exec::program_id(),
+                NFTAction::Clear { transaction_hash },
+                0,
+                self.transaction_ttl,
+            )
+            .expect("Failed to schedule clearing the transaction");
+        }
+    }
+
+    fn list(
+        &mut self,
+        token_id: TokenId,
+        price: u128,
+        currency: ActorId,
+        quantity: u32,
+    ) -> Result<NFTEvent, NFTError> {
+        self.check_token_owner(token_id)?;
+        if currency == ZERO_ID {
+            return Err(NFTError::ZeroAddress);
+        }
+        // Every token is unique, so it can only be sold once.
+        if quantity != 1 {
+            return Err(NFTError::InvalidQuantity);
+        }
+        let listing = Listing {
+            price,
+            currency,
+            quantity,
+            active: true,
+        };
+        self.listings.insert(token_id, listing.clone());
+        Ok(NFTEvent::Listed { token_id, listing })
+    }
+
+    fn delist(&mut self, token_id: TokenId) -> Result<NFTEvent, NFTError> {
+        self.check_token_owner(token_id)?;
+        self.listing_mut(token_id)?.active = false;
+        Ok(NFTEvent::Delisted { token_id })
+    }
+
+    fn update_price(&mut self, token_id: TokenId, price: u128) -> Result<NFTEvent, NFTError> {
+        self.check_token_owner(token_id)?;
+        self.listing_mut(token_id)?.price = price;
+        Ok(NFTEvent::PriceUpdated { token_id, price })
+    }
+
+    async fn buy(&mut self, token_id: TokenId) -> Result<NFTEvent, NFTError> {
+        let buyer = msg::source();
+        let listing = self
+            .listings
+            .get(&token_id)
+            .filter(|listing| listing.active)
+            .cloned()
+            .ok_or(NFTError::NotForSale)?;
+        let seller = self.token_owner(token_id)?;
+        if buyer == seller {
+            return Err(NFTError::SelfPurchase);
+        }
+
+        // The program holds the token until the payment settles,
+        // so the seller can't move it while waiting for the reply.
+        self.listings.remove(&token_id);
+        self.move_token(&seller, &exec::program_id(), token_id);
+        let purchase = PendingPurchase {
+            buyer,
+            seller,
+            listing,
+            transaction_id: self.purchase_id,
+        };
+        self.purchase_id = self.purchase_id.wrapping_add(1);
+        self.settle_purchase(token_id, purchase).await
+    }
+
+    async fn resolve_purchase(&mut self, token_id: TokenId) -> Result<NFTEvent, NFTError> {
+        // Removed while resolving, so the purchase can't be settled twice at once.
+        let purchase = self
+            .pending_purchases
+            .remove(&token_id)
+            .ok_or(NFTError::NoPendingPurchase)?;
+        self.settle_purchase(token_id, purchase).await
+    }
+
+    /// Pays the price from the buyer to the seller and the royalty accounts in a single
+    /// currency transaction, then hands the escrowed token to the buyer or back to the seller.
+    /// The purchase is left pending if it's unknown whether the payment was made.
+    async fn settle_purchase(
+        &mut self,
+        token_id: TokenId,
+        purchase: PendingPurchase,
+    ) -> Result<NFTEvent, NFTError> {
+        let payment = FTAction::BatchTransferFrom {
+            transaction_id: Some(purchase.transaction_id),
+            from: purchase.buyer,
+            transfers: self.payouts(&purchase.seller, purchase.listing.price),
+        };
+        let program_id = exec::program_id();
+        match send_payment(purchase.listing.currency, payment).await {
+            Payment::Paid => {
+                self.move_token(&program_id, &purchase.buyer, token_id);
+                Ok(NFTEvent::Sold {
+                    token_id,
+                    seller: purchase.seller,
+                    buyer: purchase.buyer,
+                    price: purchase.listing.price,
+                })
+            }
+            Payment::Rejected => {
+                self.move_token(&program_id, &purchase.seller, token_id);
+                self.listings.insert(token_id, purchase.listing);
+                Err(NFTError::PaymentFailed)
+            }
+            Payment::Unknown => {
+                self.pending_purchases.insert(token_id, purchase);
+                Err(NFTError::PaymentPending)
+            }
+        }
+    }
+
+    /// Splits `price` between `seller` and the royalty accounts.
+    fn payouts(&self, seller: &ActorId, price: u128) -> Vec<(ActorId, u128)> {
+        let mut payouts = NFTCore::nft_payout(self, seller, price);
+        // The rounding remainder of the royalties goes to the seller.
+        let paid_out: u128 = payouts.values().sum();
+        *payouts.entry(*seller).or_default() += price - paid_out;
+        payouts
+            .into_iter()
+            .filter(|(_account, amount)| *amount > 0)
+            .collect()
+    }
+
+    /// Moves `token_id` bypassing the approval checks of `NFTCore::transfer`.
+    fn move_token(&mut self, from: &ActorId, to: &ActorId, token_id: TokenId) {
+        let token = &mut self.token;
+        token.owner_by_id.insert(token_id, *to);
+        token.token_approvals.remove(&token_id);
+        if let Some(tokens) = token.tokens_for_owner.get_mut(from) {
+            tokens.retain(|id| *id != token_id);
+        }
+        token
+            .tokens_for_owner
+            .entry(*to)
+            .or_default()
+            .push(token_id);
+    }
+
+    fn listing_mut(&mut self, token_id: TokenId) -> Result<&mut Listing, NFTError> {
+        self.listings.get_mut(&token_id).ok_or(NFTError::NotListed)
+    }
+
+    fn token_owner(&self, token_id: TokenId) -> Result<ActorId, NFTError> {
+        self.token
+            .owner_by_id
+            .get(&token_id)
+            .copied()
+            .ok_or(NFTError::TokenNotFound)
+    }
+
+    fn is_approved(&self, account: &ActorId, token_id: TokenId) -> bool {
+        self.token
+            .token_approvals
+            .get(&token_id)
+            .map_or(false, |approvals| approvals.contains(account))
+    }
+
+    fn check_token_owner(&self, token_id: TokenId) -> Result<(), NFTError> {
+        if self.token_owner(token_id)? != msg::source() {
+            return Err(NFTError::NotTokenOwner);
+        }
+        Ok(())
+    }
+
+    /// Mirrors the checks of `NFTCore::transfer`, which panics on failure.
+    fn check_can_transfer(&self, to: &ActorId, token_id: TokenId) -> Result<(), NFTError> {
+        if to == &ZERO_ID {
+            return Err(NFTError::ZeroAddress);
+        }
+        let source = msg::source();
+        if self.token_owner(token_id)? != source && !self.is_approved(&source, token_id) {
+            return Err(NFTError::NotApproved);
+        }
+        Ok(())
+    }
+
+    fn add_minter(&mut self, minter: ActorId) -> Result<NFTEvent, NFTError> {
+        self.check_owner()?;
+        self.minters.insert(minter);
+        Ok(NFTEvent::MinterAdded { minter })
+    }
+
+    fn remove_minter(&mut self, minter: ActorId) -> Result<NFTEvent, NFTError> {
+        self.check_owner()?;
+        self.minters.remove(&minter);
+        Ok(NFTEvent::MinterRemoved { minter })
+    }
+
+    fn transfer_ownership(&mut self, new_owner: ActorId) -> Result<NFTEvent, NFTError> {
+        self.check_owner()?;
+        if new_owner == ZERO_ID {
+            return Err(NFTError::ZeroAddress);
+        }
+        let previous_owner = self.owner;
+        self.owner = new_owner;
+        Ok(NFTEvent::OwnershipTransferred {
+            previous_owner,
+            new_owner,
+        })
+    }
+
+    /// Mirrors the checks of `NFTCore::delegated_approve`, which panics on failure.
+    fn check_delegated_approve(
+        &self,
+        message: &DelegatedApproveMessage,
+        signature: &[u8; 64],
+    ) -> Result<(), NFTError> {
+        if self.token_owner(message.token_id)? != message.token_owner_id {
+            return Err(NFTError::NotTokenOwner);
+        }
+        if message.approved_actor_id != msg::source() {
+            return Err(NFTError::WrongApprovedActor);
+        }
+        if message.nft_program_id != exec::program_id() {
+            return Err(NFTError::WrongProgram);
+        }
+        if exec::block_timestamp() >= message.expiration_timestamp {
+            return Err(NFTError::ApprovalExpired);
+        }
+
+        let owner: [u8; 32] = message.token_owner_id.into();
+        let public_key =
+            schnorrkel::PublicKey::from_bytes(&owner).map_err(|_| NFTError::InvalidSignature)?;
+        let signature =
+            schnorrkel::Signature::from_bytes(signature).map_err(|_| NFTError::InvalidSignature)?;
+        public_key
+            .verify_simple(SIGNING_CONTEXT, &message.encode(), &signature)
+            .map_err(|_| NFTError::InvalidSignature)
+    }
+
+    fn check_owner(&self) -> Result<(), NFTError> {
+        if msg::source() != self.owner {
+            return Err(NFTError::NotCollectionOwner);
+        }
+        Ok(())
+    }
+
+    /// Executed on receiving the delayed `NFTAction::Clear`.
+    /// Removes the transaction only once it has expired, as it may have been cached again since.
+    fn clear(&mut self, transaction_hash: H256) -> Result<NFTEvent, NFTError> {
+        if msg::source() != exec::program_id() {
+            return Err(NFTError::NotAllowedToClear);
+        }
+        let cleared = NFTEvent::TransactionCleared { transaction_hash };
+        let Some(transaction) = self.transactions.get(&transaction_hash) else {
+            return Ok(cleared);
+        };
+        // An evicted and retried transaction is cleared by its own delayed message.
+        let expires_at = transaction
+            .block_height
+            .saturating_add(self.transaction_ttl);
+        if exec::block_height() < expires_at {
+            return Ok(cleared);
+        }
+        let account = transaction.account;
         self.transactions.remove(&transaction_hash);
+        if let Some(hashes) = self.transactions_by_account.get_mut(&account) {
+            hashes.retain(|hash| *hash != transaction_hash);
+            if hashes.is_empty() {
+                self.transactions_by_account.remove(&account);
+            }
+        }
+        Ok(cleared)
     }
 }
 
@@ -217,6 +516,30 @@ fn reply(payload: impl Encode) -> GstdResult<MessageId> {
     msg::reply(payload, 0)
 }
 
+/// Outcome of a payment sent to the currency.
+enum Payment {
+    Paid,
+    Rejected,
+    /// The currency didn't reply in time or its reply couldn't be read.
+    Unknown,
+}
+
+/// Sends `payment` to the `currency` fungible token, waiting up to `PAYMENT_TIMEOUT` blocks.
+async fn send_payment(currency: ActorId, payment: FTAction) -> Payment {
+    let Ok(reply) = msg::send_for_reply_as::<_, Result<FTEvent, FTError>>(currency, payment, 0)
+    else {
+        return Payment::Rejected;
+    };
+    let Ok(reply) = reply.up_to(Some(PAYMENT_TIMEOUT)) else {
+        return Payment::Unknown;
+    };
+    match reply.await {
+        Ok(Ok(_event)) => Payment::Paid,
+        Ok(Err(_error)) => Payment::Rejected,
+        Err(_error) => Payment::Unknown,
+    }
+}
+
 pub fn get_hash(account: &ActorId, transaction_id: u64) -> H256 {
     let account: [u8; 32] = (*account).into();
     let transaction_id = transaction_id.to_be_bytes();
@@ -230,17 +553,38 @@ impl From<&Nft> for IoNFT {
             token_id,
             owner,
             transactions,
+            transactions_by_account: _,
+            transaction_ttl,
+            max_transactions,
+            minters,
+            listings,
+            pending_purchases,
+            purchase_id: _,
         } = value;
 
         let transactions = transactions
             .iter()
-            .map(|(key, event)| (*key, event.clone()))
+            .map(|(key, transaction)| (*key, transaction.clone()))
+            .collect();
+        let mut listings: Vec<_> = listings
+            .iter()
+            .map(|(id, listing)| (*id, listing.clone()))
+            .collect();
+        listings.sort_unstable_by_key(|(id, _listing)| *id);
+        let pending_purchases = pending_purchases
+            .iter()
+            .map(|(id, purchase)| (*id, purchase.clone()))
             .collect();
         Self {
             token: token.into(),
             token_id: *token_id,
             owner: *owner,
             transactions,
+            transaction_ttl: *transaction_ttl,
+            max_transactions: *max_transactions,
+            minters: minters.iter().copied().collect(),
+            listings,
+            pending_purchases,
         }
     }
 }
@@ -1,5 +1,8 @@
 #![no_std]
 
+#[cfg(test)]
+mod tests;
+
 #[cfg(not(feature = "binary-vendor"))]
 mod contract;
 
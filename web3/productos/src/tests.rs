@@ -0,0 +1,871 @@
+use ft_io::{FTAction, FTError, FTEvent, FTQuery, FTQueryReply, InitConfig};
+use gear_lib::non_fungible_token::{io::NFTTransfer, royalties::Royalties, token::*};
+use gstd::{prelude::*, ActorId, Decode, Encode};
+use gtest::{Program, System, WasmProgram};
+use nft_io::*;
+use primitive_types::H256;
+use sp_core::{sr25519::Pair, Pair as _};
+const USERS: &[u64] = &[3, 4, 5];
+const NFT_ID: u64 = 1;
+const CURRENCY: u64 = 100;
+const ROYALTY: u64 = 6;
+const ZERO_ID: ActorId = ActorId::new([0u8; 32]);
+
+fn ok(event: NFTEvent) -> Vec<u8> {
+    Ok::<NFTEvent, NFTError>(event).encode()
+}
+
+fn err(error: NFTError) -> Vec<u8> {
+    Err::<NFTEvent, NFTError>(error).encode()
+}
+
+fn init_config() -> InitNFT {
+    InitNFT {
+        name: String::from("Productos"),
+        symbol: String::from("PRD"),
+        base_uri: String::from("ipfs://"),
+        royalties: None,
+        transaction_ttl: 0,
+        max_transactions: 16,
+    }
+}
+
+fn init_nft(sys: &System) -> Program<'_> {
+    init_nft_with(sys, init_config())
+}
+
+fn init_nft_with(sys: &System, config: InitNFT) -> Program<'_> {
+    sys.init_logger();
+
+    let nft = Program::current(sys);
+    let res = nft.send(USERS[0], config);
+    assert!(res.log().is_empty());
+    nft
+}
+
+fn mint_action(transaction_id: u64) -> NFTAction {
+    NFTAction::Mint {
+        transaction_id,
+        token_metadata: TokenMetadata {
+            name: String::from("Product"),
+            description: String::from("Product description"),
+            media: String::from("ipfs://media"),
+            reference: String::from("ipfs://reference"),
+        },
+    }
+}
+
+fn minted(to: u64, token_id: u64) -> Vec<u8> {
+    ok(NFTEvent::Transfer(NFTTransfer {
+        from: ZERO_ID,
+        to: to.into(),
+        token_id: token_id.into(),
+    }))
+}
+
+#[test]
+fn mint_by_minters() {
+    let sys = System::new();
+    let nft = init_nft(&sys);
+
+    // must fail since only the owner and the minters can mint
+    let res = nft.send(USERS[1], mint_action(0));
+    assert!(res.contains(&(USERS[1], err(NFTError::NotMinter))));
+
+    let res = nft.send(USERS[0], mint_action(0));
+    assert!(res.contains(&(USERS[0], minted(USERS[0], 0))));
+
+    // must fail since only the owner can manage the minters
+    let res = nft.send(
+        USERS[1],
+        NFTAction::AddMinter {
+            minter: USERS[1].into(),
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(NFTError::NotCollectionOwner))));
+
+    let res = nft.send(
+        USERS[0],
+        NFTAction::AddMinter {
+            minter: USERS[1].into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(NFTEvent::MinterAdded {
+            minter: USERS[1].into(),
+        })
+    )));
+    let res = nft.send(USERS[1], mint_action(1));
+    assert!(res.contains(&(USERS[1], minted(USERS[1], 1))));
+
+    let res = nft.send(
+        USERS[0],
+        NFTAction::RemoveMinter {
+            minter: USERS[1].into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(NFTEvent::MinterRemoved {
+            minter: USERS[1].into(),
+        })
+    )));
+    // must fail since the minter was removed
+    let res = nft.send(USERS[1], mint_action(2));
+    assert!(res.contains(&(USERS[1], err(NFTError::NotMinter))));
+}
+
+#[test]
+fn transfer_ownership() {
+    let sys = System::new();
+    let nft = init_nft(&sys);
+
+    // must fail since only the owner can transfer the ownership
+    let res = nft.send(
+        USERS[1],
+        NFTAction::TransferOwnership {
+            new_owner: USERS[1].into(),
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(NFTError::NotCollectionOwner))));
+
+    // must fail since the new owner is the zero address
+    let res = nft.send(
+        USERS[0],
+        NFTAction::TransferOwnership { new_owner: ZERO_ID },
+    );
+    assert!(res.contains(&(USERS[0], err(NFTError::ZeroAddress))));
+
+    let res = nft.send(
+        USERS[0],
+        NFTAction::TransferOwnership {
+            new_owner: USERS[1].into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(NFTEvent::OwnershipTransferred {
+            previous_owner: USERS[0].into(),
+            new_owner: USERS[1].into(),
+        })
+    )));
+
+    // the previous owner can neither mint nor manage the minters anymore
+    let res = nft.send(USERS[0], mint_action(0));
+    assert!(res.contains(&(USERS[0], err(NFTError::NotMinter))));
+    let res = nft.send(
+        USERS[0],
+        NFTAction::AddMinter {
+            minter: USERS[2].into(),
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(NFTError::NotCollectionOwner))));
+
+    let res = nft.send(
+        USERS[1],
+        NFTAction::AddMinter {
+            minter: USERS[2].into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(NFTEvent::MinterAdded {
+            minter: USERS[2].into(),
+        })
+    )));
+    let res = nft.send(USERS[2], mint_action(0));
+    assert!(res.contains(&(USERS[2], minted(USERS[2], 0))));
+}
+
+#[test]
+fn transfer_failures() {
+    let sys = System::new();
+    let nft = init_nft(&sys);
+    let res = nft.send(USERS[0], mint_action(0));
+    assert!(res.contains(&(USERS[0], minted(USERS[0], 0))));
+
+    let transfer = |transaction_id| NFTAction::Transfer {
+        transaction_id,
+        to: USERS[2].into(),
+        token_id: 0.into(),
+    };
+    // must fail since `USERS[1]` isn't approved for the token
+    let res = nft.send(USERS[1], transfer(0));
+    assert!(res.contains(&(USERS[1], err(NFTError::NotApproved))));
+
+    let res = nft.send(
+        USERS[0],
+        NFTAction::Approve {
+            transaction_id: 1,
+            to: USERS[1].into(),
+            token_id: 0.into(),
+        },
+    );
+    assert!(!res.main_failed());
+
+    // must fail since the failed transaction is cached with its error
+    let res = nft.send(USERS[1], transfer(0));
+    assert!(res.contains(&(USERS[1], err(NFTError::NotApproved))));
+
+    let res = nft.send(USERS[1], transfer(1));
+    assert!(res.contains(&(
+        USERS[1],
+        ok(NFTEvent::Transfer(NFTTransfer {
+            from: USERS[0].into(),
+            to: USERS[2].into(),
+            token_id: 0.into(),
+        }))
+    )));
+}
+
+#[test]
+fn missing_token() {
+    let sys = System::new();
+    let nft = init_nft(&sys);
+    let res = nft.send(USERS[0], mint_action(0));
+    assert!(res.contains(&(USERS[0], minted(USERS[0], 0))));
+
+    // must fail since the token with id 1 wasn't minted
+    let res = nft.send(
+        USERS[0],
+        NFTAction::Burn {
+            transaction_id: 1,
+            token_id: 1.into(),
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(NFTError::TokenNotFound))));
+    let res = nft.send(
+        USERS[0],
+        NFTAction::Transfer {
+            transaction_id: 2,
+            to: USERS[1].into(),
+            token_id: 1.into(),
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(NFTError::TokenNotFound))));
+    let res = nft.send(USERS[0], NFTAction::Owner { token_id: 1.into() });
+    assert!(res.contains(&(USERS[0], err(NFTError::TokenNotFound))));
+    let res = nft.send(
+        USERS[0],
+        NFTAction::IsApproved {
+            to: USERS[1].into(),
+            token_id: 1.into(),
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(NFTError::TokenNotFound))));
+
+    // the burned token can't be found anymore
+    let res = nft.send(
+        USERS[0],
+        NFTAction::Burn {
+            transaction_id: 3,
+            token_id: 0.into(),
+        },
+    );
+    assert!(!res.main_failed());
+    let res = nft.send(USERS[0], NFTAction::Owner { token_id: 0.into() });
+    assert!(res.contains(&(USERS[0], err(NFTError::TokenNotFound))));
+}
+
+fn listing(price: u128) -> Listing {
+    Listing {
+        price,
+        currency: CURRENCY.into(),
+        quantity: 1,
+        active: true,
+    }
+}
+
+fn list_action(token_id: u64, price: u128) -> NFTAction {
+    NFTAction::List {
+        token_id: token_id.into(),
+        price,
+        currency: CURRENCY.into(),
+        quantity: 1,
+    }
+}
+
+/// Reads the active listings by the `listed_tokens` state function.
+fn listed_tokens(nft: &Program, offset: u64, limit: u64) -> Vec<(TokenId, Listing)> {
+    let listed: Vec<(Token, Listing)> = nft
+        .read_state_using_wasm(
+            "listed_tokens",
+            nft_state::WASM_BINARY.into(),
+            Some((offset, limit)),
+        )
+        .expect("Unable to read the listed tokens");
+    listed
+        .into_iter()
+        .map(|(token, listing)| (token.id, listing))
+        .collect()
+}
+
+#[test]
+fn list_and_delist() {
+    let sys = System::new();
+    let nft = init_nft(&sys);
+    for transaction_id in 0..3 {
+        nft.send(USERS[0], mint_action(transaction_id));
+    }
+
+    // must fail since only the token owner can list it
+    let res = nft.send(USERS[1], list_action(0, 1000));
+    assert!(res.contains(&(USERS[1], err(NFTError::NotTokenOwner))));
+
+    // must fail since every token is unique
+    let res = nft.send(
+        USERS[0],
+        NFTAction::List {
+            token_id: 0.into(),
+            price: 1000,
+            currency: CURRENCY.into(),
+            quantity: 2,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(NFTError::InvalidQuantity))));
+
+    // must fail since the currency is the zero address
+    let res = nft.send(
+        USERS[0],
+        NFTAction::List {
+            token_id: 0.into(),
+            price: 1000,
+            currency: ZERO_ID,
+            quantity: 1,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(NFTError::ZeroAddress))));
+
+    for token_id in 0..3 {
+        let res = nft.send(USERS[0], list_action(token_id, 1000));
+        assert!(res.contains(&(
+            USERS[0],
+            ok(NFTEvent::Listed {
+                token_id: token_id.into(),
+                listing: listing(1000),
+            })
+        )));
+    }
+
+    // must fail since only the token owner can update the price
+    let res = nft.send(
+        USERS[1],
+        NFTAction::UpdatePrice {
+            token_id: 0.into(),
+            price: 1,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(NFTError::NotTokenOwner))));
+
+    let res = nft.send(
+        USERS[0],
+        NFTAction::UpdatePrice {
+            token_id: 0.into(),
+            price: 1500,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(NFTEvent::PriceUpdated {
+            token_id: 0.into(),
+            price: 1500,
+        })
+    )));
+
+    // must fail since only the token owner can delist it
+    let res = nft.send(USERS[1], NFTAction::Delist { token_id: 1.into() });
+    assert!(res.contains(&(USERS[1], err(NFTError::NotTokenOwner))));
+
+    let res = nft.send(USERS[0], NFTAction::Delist { token_id: 1.into() });
+    assert!(res.contains(&(USERS[0], ok(NFTEvent::Delisted { token_id: 1.into() }))));
+
+    // delisted tokens are skipped by the pagination
+    assert_eq!(
+        listed_tokens(&nft, 0, 10),
+        vec![
+            (TokenId::from(0u64), listing(1500)),
+            (TokenId::from(2u64), listing(1000))
+        ]
+    );
+    assert_eq!(
+        listed_tokens(&nft, 1, 1),
+        vec![(TokenId::from(2u64), listing(1000))]
+    );
+    assert!(listed_tokens(&nft, 2, 10).is_empty());
+}
+
+#[test]
+fn listing_removed_on_transfer_and_burn() {
+    let sys = System::new();
+    let nft = init_nft(&sys);
+    for transaction_id in 0..2 {
+        nft.send(USERS[0], mint_action(transaction_id));
+        nft.send(USERS[0], list_action(transaction_id, 1000));
+    }
+
+    let res = nft.send(
+        USERS[0],
+        NFTAction::Transfer {
+            transaction_id: 2,
+            to: USERS[1].into(),
+            token_id: 0.into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(NFTEvent::Transfer(NFTTransfer {
+            from: USERS[0].into(),
+            to: USERS[1].into(),
+            token_id: 0.into(),
+        }))
+    )));
+    // must fail since the listing was removed with the transfer
+    let res = nft.send(
+        USERS[1],
+        NFTAction::UpdatePrice {
+            token_id: 0.into(),
+            price: 1,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(NFTError::NotListed))));
+
+    let res = nft.send(
+        USERS[0],
+        NFTAction::Burn {
+            transaction_id: 3,
+            token_id: 1.into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(NFTEvent::Transfer(NFTTransfer {
+            from: USERS[0].into(),
+            to: ZERO_ID,
+            token_id: 1.into(),
+        }))
+    )));
+    assert!(listed_tokens(&nft, 0, 10).is_empty());
+}
+
+/// Deploys the coin as the listing currency and funds the buyer with it.
+fn init_currency(sys: &System) -> Program<'_> {
+    let ft = Program::from_opt_and_meta_code_with_id(
+        sys,
+        CURRENCY,
+        fungible_token::WASM_BINARY_OPT.into(),
+        None,
+    );
+    let res = ft.send(
+        USERS[2],
+        InitConfig {
+            name: String::from("MyToken"),
+            symbol: String::from("MTK"),
+            decimals: 18,
+            icon: String::new(),
+            description: String::new(),
+            website: String::new(),
+            max_supply: None,
+            admin: USERS[2].into(),
+            fee_bps: 0,
+            treasury: ZERO_ID,
+            fee_exempt: vec![],
+            wrapped_native: false,
+        },
+    );
+    assert!(res.log().is_empty());
+    ft.send(
+        USERS[2],
+        FTAction::Mint {
+            transaction_id: None,
+            amount: 10000,
+        },
+    );
+    ft.send(
+        USERS[2],
+        FTAction::Transfer {
+            transaction_id: None,
+            to: USERS[1].into(),
+            amount: 10000,
+        },
+    );
+    ft
+}
+
+/// Mints the token 0 to the owner and lists it for 1000 of the currency.
+fn init_sale(sys: &System, config: InitNFT) -> Program<'_> {
+    let nft = init_nft_with(sys, config);
+    nft.send(USERS[0], mint_action(0));
+    nft.send(USERS[0], list_action(0, 1000));
+    nft
+}
+
+fn approve_payment(ft: &Program, amount: u128) {
+    ft.send(
+        USERS[1],
+        FTAction::Approve {
+            transaction_id: None,
+            to: NFT_ID.into(),
+            amount,
+        },
+    );
+}
+
+fn ft_balance(ft: &Program, account: u64) -> u128 {
+    let res = ft.send(
+        USERS[0],
+        FTAction::Query(FTQuery::BalanceOf(account.into())),
+    );
+    match Result::<FTEvent, FTError>::decode(&mut res.log()[0].payload()) {
+        Ok(FTEvent::QueryReply(FTQueryReply::Balance(balance))) => balance,
+        reply => panic!("Unexpected reply: {reply:?}"),
+    }
+}
+
+fn assert_owner(nft: &Program, token_id: u64, owner: u64) {
+    let res = nft.send(
+        USERS[0],
+        NFTAction::Owner {
+            token_id: token_id.into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(NFTEvent::Owner {
+            owner: owner.into(),
+            token_id: token_id.into(),
+        })
+    )));
+}
+
+fn sold(price: u128) -> Vec<u8> {
+    ok(NFTEvent::Sold {
+        token_id: 0.into(),
+        seller: USERS[0].into(),
+        buyer: USERS[1].into(),
+        price,
+    })
+}
+
+#[test]
+fn buy() {
+    let sys = System::new();
+    let nft = init_sale(&sys, init_config());
+    let ft = init_currency(&sys);
+    approve_payment(&ft, 1000);
+
+    // must fail since the seller can't buy its own token
+    let res = nft.send(USERS[0], NFTAction::Buy { token_id: 0.into() });
+    assert!(res.contains(&(USERS[0], err(NFTError::SelfPurchase))));
+
+    let res = nft.send(USERS[1], NFTAction::Buy { token_id: 0.into() });
+    assert!(res.contains(&(USERS[1], sold(1000))));
+    assert_owner(&nft, 0, USERS[1]);
+    assert_eq!(ft_balance(&ft, USERS[0]), 1000);
+    assert_eq!(ft_balance(&ft, USERS[1]), 9000);
+    assert!(listed_tokens(&nft, 0, 10).is_empty());
+
+    // must fail since the token was sold
+    let res = nft.send(USERS[2], NFTAction::Buy { token_id: 0.into() });
+    assert!(res.contains(&(USERS[2], err(NFTError::NotForSale))));
+}
+
+#[test]
+fn buy_with_royalties() {
+    let sys = System::new();
+    let nft = init_sale(
+        &sys,
+        InitNFT {
+            royalties: Some(Royalties {
+                accounts: BTreeMap::from([(ROYALTY.into(), 10000)]),
+                percent: 1000,
+            }),
+            ..init_config()
+        },
+    );
+    let ft = init_currency(&sys);
+    approve_payment(&ft, 1000);
+
+    let res = nft.send(USERS[1], NFTAction::Buy { token_id: 0.into() });
+    assert!(res.contains(&(USERS[1], sold(1000))));
+    // 10% of the price goes to the royalty account
+    assert_eq!(ft_balance(&ft, USERS[0]), 900);
+    assert_eq!(ft_balance(&ft, ROYALTY), 100);
+    assert_eq!(ft_balance(&ft, USERS[1]), 9000);
+}
+
+#[test]
+fn buy_without_allowance() {
+    let sys = System::new();
+    let nft = init_sale(&sys, init_config());
+    let ft = init_currency(&sys);
+
+    // must fail since the buyer didn't approve the payment
+    let res = nft.send(USERS[1], NFTAction::Buy { token_id: 0.into() });
+    assert!(res.contains(&(USERS[1], err(NFTError::PaymentFailed))));
+
+    // the token and its listing are returned to the seller
+    assert_owner(&nft, 0, USERS[0]);
+    assert_eq!(
+        listed_tokens(&nft, 0, 10),
+        vec![(TokenId::from(0u64), listing(1000))]
+    );
+    assert_eq!(ft_balance(&ft, USERS[1]), 10000);
+}
+
+#[test]
+fn buy_with_rejected_payout() {
+    let sys = System::new();
+    let nft = init_sale(&sys, init_config());
+    let ft = init_currency(&sys);
+    approve_payment(&ft, 1000);
+    ft.send(USERS[2], FTAction::Freeze(USERS[0].into()));
+
+    // must fail since the seller can't receive the payment
+    let res = nft.send(USERS[1], NFTAction::Buy { token_id: 0.into() });
+    assert!(res.contains(&(USERS[1], err(NFTError::PaymentFailed))));
+
+    // nothing was paid, so the buyer keeps both the tokens and the allowance
+    assert_owner(&nft, 0, USERS[0]);
+    assert_eq!(ft_balance(&ft, USERS[0]), 0);
+    assert_eq!(ft_balance(&ft, USERS[1]), 10000);
+
+    ft.send(USERS[2], FTAction::Unfreeze(USERS[0].into()));
+    let res = nft.send(USERS[1], NFTAction::Buy { token_id: 0.into() });
+    assert!(res.contains(&(USERS[1], sold(1000))));
+}
+
+/// Currency that doesn't reply to the first payment, then accepts its retries.
+#[derive(Debug, Default)]
+struct SilentCurrency(Option<Option<u64>>);
+
+impl WasmProgram for SilentCurrency {
+    fn init(&mut self, _payload: Vec<u8>) -> Result<Option<Vec<u8>>, &'static str> {
+        Ok(None)
+    }
+
+    fn handle(&mut self, payload: Vec<u8>) -> Result<Option<Vec<u8>>, &'static str> {
+        let Ok(FTAction::BatchTransferFrom {
+            transaction_id,
+            from,
+            ..
+        }) = FTAction::decode(&mut payload.as_slice())
+        else {
+            return Err("Unexpected payment");
+        };
+        match self.0.replace(transaction_id) {
+            None => Ok(None),
+            Some(first_id) if first_id == transaction_id => Ok(Some(
+                Ok::<FTEvent, FTError>(FTEvent::BatchTransfer {
+                    from,
+                    transfers: vec![],
+                })
+                .encode(),
+            )),
+            Some(_first_id) => Err("The retried payment has another transaction id"),
+        }
+    }
+
+    fn handle_reply(&mut self, _payload: Vec<u8>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    fn handle_signal(&mut self, _payload: Vec<u8>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    fn state(&mut self) -> Result<Vec<u8>, &'static str> {
+        Ok(vec![])
+    }
+}
+
+#[test]
+fn resolve_pending_purchase() {
+    let sys = System::new();
+    let nft = init_sale(&sys, init_config());
+    let currency = Program::mock_with_id(&sys, CURRENCY, SilentCurrency::default());
+    currency.send_bytes(USERS[0], b"");
+
+    // must fail since there's no purchase to resolve
+    let res = nft.send(USERS[1], NFTAction::ResolvePurchase { token_id: 0.into() });
+    assert!(res.contains(&(USERS[1], err(NFTError::NoPendingPurchase))));
+
+    nft.send(USERS[1], NFTAction::Buy { token_id: 0.into() });
+    sys.spend_blocks(PAYMENT_TIMEOUT + 1);
+    // the token stays escrowed until the payment is known
+    assert_owner(&nft, 0, NFT_ID);
+
+    let res = nft.send(USERS[1], NFTAction::ResolvePurchase { token_id: 0.into() });
+    assert!(res.contains(&(USERS[1], sold(1000))));
+    assert_owner(&nft, 0, USERS[1]);
+}
+
+fn approve_message(owner: ActorId, program: u64, expiration: u64) -> DelegatedApproveMessage {
+    DelegatedApproveMessage {
+        token_owner_id: owner,
+        approved_actor_id: USERS[1].into(),
+        nft_program_id: program.into(),
+        token_id: 0.into(),
+        expiration_timestamp: expiration,
+    }
+}
+
+fn delegated_approve_action(
+    transaction_id: u64,
+    message: DelegatedApproveMessage,
+    signer: &Pair,
+) -> NFTAction {
+    let signature = signer.sign(&message.encode()).0;
+    NFTAction::DelegatedApprove {
+        transaction_id,
+        message,
+        signature,
+    }
+}
+
+#[test]
+fn delegated_approve() {
+    let sys = System::new();
+    let nft = init_nft(&sys);
+    let owner = Pair::from_seed(&[1; 32]);
+    let owner_id = ActorId::from(owner.public().0);
+    nft.send(USERS[0], mint_action(0));
+    nft.send(
+        USERS[0],
+        NFTAction::Transfer {
+            transaction_id: 1,
+            to: owner_id,
+            token_id: 0.into(),
+        },
+    );
+    let message = || approve_message(owner_id, NFT_ID, u64::MAX);
+
+    // must fail since only the approved actor can submit the approval
+    let res = nft.send(USERS[2], delegated_approve_action(0, message(), &owner));
+    assert!(res.contains(&(USERS[2], err(NFTError::WrongApprovedActor))));
+
+    // must fail since the approval was signed for another program
+    let res = nft.send(
+        USERS[1],
+        delegated_approve_action(0, approve_message(owner_id, CURRENCY, u64::MAX), &owner),
+    );
+    assert!(res.contains(&(USERS[1], err(NFTError::WrongProgram))));
+
+    // must fail since the approval has expired
+    let res = nft.send(
+        USERS[1],
+        delegated_approve_action(1, approve_message(owner_id, NFT_ID, 0), &owner),
+    );
+    assert!(res.contains(&(USERS[1], err(NFTError::ApprovalExpired))));
+
+    // must fail since the approval isn't signed by the token owner
+    let res = nft.send(
+        USERS[1],
+        delegated_approve_action(2, message(), &Pair::from_seed(&[2; 32])),
+    );
+    assert!(res.contains(&(USERS[1], err(NFTError::InvalidSignature))));
+
+    let res = nft.send(USERS[1], delegated_approve_action(3, message(), &owner));
+    let reply = Result::<NFTEvent, NFTError>::decode(&mut res.log()[0].payload())
+        .expect("Unable to decode the reply");
+    assert!(matches!(reply, Ok(NFTEvent::Approval(_))));
+    let res = nft.send(
+        USERS[1],
+        NFTAction::IsApproved {
+            to: USERS[1].into(),
+            token_id: 0.into(),
+        },
+    );
+    assert!(res.contains(&(
+        USERS[1],
+        ok(NFTEvent::IsApproved {
+            to: USERS[1].into(),
+            token_id: 0.into(),
+            approved: true,
+        })
+    )));
+}
+
+#[test]
+fn clear_failures() {
+    let sys = System::new();
+    let nft = init_nft(&sys);
+
+    // must fail since only the program itself can clear transactions
+    let res = nft.send(
+        USERS[0],
+        NFTAction::Clear {
+            transaction_hash: H256::zero(),
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(NFTError::NotAllowedToClear))));
+}
+
+/// Mints by the collection owner, checking which token the reply is about.
+/// A cached transaction is replayed, so its retry replies with the already minted token.
+fn assert_mint(nft: &Program, transaction_id: u64, token_id: u64) {
+    let res = nft.send(USERS[0], mint_action(transaction_id));
+    assert!(res.contains(&(USERS[0], minted(USERS[0], token_id))));
+}
+
+#[test]
+fn evict_oldest_transaction() {
+    let sys = System::new();
+    let nft = init_nft_with(
+        &sys,
+        InitNFT {
+            max_transactions: 1,
+            ..init_config()
+        },
+    );
+
+    assert_mint(&nft, 0, 0);
+    assert_mint(&nft, 0, 0);
+    // caching the second transaction evicts the first one
+    assert_mint(&nft, 1, 1);
+    assert_mint(&nft, 1, 1);
+    assert_mint(&nft, 0, 2);
+}
+
+#[test]
+fn clear_expired_transaction() {
+    let sys = System::new();
+    let nft = init_nft_with(
+        &sys,
+        InitNFT {
+            transaction_ttl: 10,
+            ..init_config()
+        },
+    );
+
+    assert_mint(&nft, 0, 0);
+    sys.spend_blocks(4);
+    assert_mint(&nft, 0, 0);
+    sys.spend_blocks(10);
+    // the transaction was cleared once its TTL passed
+    assert_mint(&nft, 0, 1);
+}
+
+#[test]
+fn keep_recached_transaction() {
+    let sys = System::new();
+    let nft = init_nft_with(
+        &sys,
+        InitNFT {
+            transaction_ttl: 10,
+            max_transactions: 1,
+            ..init_config()
+        },
+    );
+
+    assert_mint(&nft, 0, 0);
+    sys.spend_blocks(4);
+    // the first transaction is evicted and then cached again
+    assert_mint(&nft, 1, 1);
+    assert_mint(&nft, 0, 2);
+    sys.spend_blocks(6);
+    // the clearing scheduled by the evicted entry must not clear the new one
+    assert_mint(&nft, 0, 2);
+    sys.spend_blocks(10);
+    assert_mint(&nft, 0, 3);
+}
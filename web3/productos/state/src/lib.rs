@@ -6,7 +6,10 @@ use gear_lib::non_fungible_token::{
 };
 use gmeta::{metawasm, Metadata};
 use gstd::{ActorId, Vec};
-use nft_io::NFTMetadata;
+use nft_io::{Listing, NFTMetadata};
+
+#[cfg(feature = "binary-vendor")]
+include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
 #[metawasm]
 pub mod metafns {
@@ -38,6 +41,35 @@ pub mod metafns {
         }
         tokens
     }
+
+    pub fn owner(state: State) -> ActorId {
+        state.owner
+    }
+
+    pub fn minters(state: State) -> Vec<ActorId> {
+        state.minters
+    }
+
+    pub fn listing(state: State, token_id: TokenId) -> Option<Listing> {
+        state
+            .listings
+            .binary_search_by_key(&token_id, |(id, _listing)| *id)
+            .ok()
+            .map(|index| state.listings[index].1.clone())
+    }
+
+    /// Tokens with active listings, `limit` of them starting from `offset`.
+    pub fn listed_tokens(state: State, offset: u64, limit: u64) -> Vec<(Token, Listing)> {
+        state
+            .listings
+            .iter()
+            .filter(|(_id, listing)| listing.active)
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(id, listing)| (token_helper(id, &state), listing.clone()))
+            .collect()
+    }
+
     pub fn total_supply(state: State) -> u128 {
         state.token.owner_by_id.len() as u128
     }
@@ -0,0 +1,9 @@
+use gear_wasm_builder::WasmBuilder;
+use gmeta::Metadata;
+use registry_io::RegistryMetadata;
+
+fn main() {
+    WasmBuilder::with_meta(RegistryMetadata::repr())
+        .exclude_features(["binary-vendor"])
+        .build();
+}
@@ -0,0 +1,82 @@
+#![no_std]
+
+use gmeta::{In, InOut, Metadata};
+use gstd::{prelude::*, ActorId};
+
+pub struct RegistryMetadata;
+
+impl Metadata for RegistryMetadata {
+    type Init = In<InitRegistry>;
+    type Handle = InOut<RegistryAction, Result<RegistryEvent, RegistryError>>;
+    type Others = ();
+    type Reply = ();
+    type Signal = ();
+    type State = IoRegistry;
+}
+
+#[derive(Debug, Decode, Encode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct InitRegistry {
+    pub admin: ActorId,
+}
+
+/// A fungible token program registered as a payment currency.
+#[derive(Debug, Clone, PartialEq, Eq, Decode, Encode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub decimals: u8,
+    /// Only enabled tokens are accepted as payment.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Decode, Encode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub enum RegistryAction {
+    AddToken {
+        token: ActorId,
+        symbol: String,
+        decimals: u8,
+    },
+    RemoveToken(ActorId),
+    SetEnabled {
+        token: ActorId,
+        enabled: bool,
+    },
+    /// Replies whether `token` is an accepted payment currency.
+    IsAccepted(ActorId),
+    TokenInfo(ActorId),
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub enum RegistryEvent {
+    TokenAdded { token: ActorId, info: TokenInfo },
+    TokenRemoved(ActorId),
+    EnabledUpdated { token: ActorId, enabled: bool },
+    Accepted(bool),
+    TokenInfo(TokenInfo),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub enum RegistryError {
+    ZeroAddress,
+    NotAdmin,
+    AlreadyRegistered,
+    NotRegistered,
+}
+
+#[derive(Debug, Clone, Default, Encode, Decode, TypeInfo)]
+#[codec(crate = gstd::codec)]
+#[scale_info(crate = gstd::scale_info)]
+pub struct IoRegistry {
+    pub admin: ActorId,
+    /// Registered tokens sorted by their ids.
+    pub tokens: Vec<(ActorId, TokenInfo)>,
+}
@@ -0,0 +1,155 @@
+use gmeta::Metadata;
+use gstd::{errors::Result as GstdResult, msg, prelude::*, ActorId, MessageId};
+use hashbrown::HashMap;
+use registry_io::*;
+
+const ZERO_ID: ActorId = ActorId::new([0u8; 32]);
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Account allowed to add and remove tokens.
+    admin: ActorId,
+    /// Registered fungible token programs.
+    tokens: HashMap<ActorId, TokenInfo>,
+}
+
+static mut REGISTRY: Option<Registry> = None;
+
+impl Registry {
+    /// Executed on receiving `registry-messages::AddTokenInput`.
+    fn add_token(
+        &mut self,
+        token: ActorId,
+        symbol: String,
+        decimals: u8,
+    ) -> Result<RegistryEvent, RegistryError> {
+        self.check_admin()?;
+        if token == ZERO_ID {
+            return Err(RegistryError::ZeroAddress);
+        }
+        if self.tokens.contains_key(&token) {
+            return Err(RegistryError::AlreadyRegistered);
+        }
+        let info = TokenInfo {
+            symbol,
+            decimals,
+            enabled: true,
+        };
+        self.tokens.insert(token, info.clone());
+        Ok(RegistryEvent::TokenAdded { token, info })
+    }
+
+    /// Executed on receiving `registry-messages::RemoveTokenInput`.
+    fn remove_token(&mut self, token: ActorId) -> Result<RegistryEvent, RegistryError> {
+        self.check_admin()?;
+        self.tokens
+            .remove(&token)
+            .ok_or(RegistryError::NotRegistered)?;
+        Ok(RegistryEvent::TokenRemoved(token))
+    }
+
+    /// Executed on receiving `registry-messages::SetEnabledInput`.
+    /// Disabled tokens stay registered but aren't accepted as payment.
+    fn set_enabled(
+        &mut self,
+        token: ActorId,
+        enabled: bool,
+    ) -> Result<RegistryEvent, RegistryError> {
+        self.check_admin()?;
+        self.token_mut(&token)?.enabled = enabled;
+        Ok(RegistryEvent::EnabledUpdated { token, enabled })
+    }
+
+    fn is_accepted(&self, token: &ActorId) -> bool {
+        self.tokens.get(token).map_or(false, |info| info.enabled)
+    }
+
+    fn token_info(&self, token: &ActorId) -> Result<RegistryEvent, RegistryError> {
+        self.tokens
+            .get(token)
+            .cloned()
+            .map(RegistryEvent::TokenInfo)
+            .ok_or(RegistryError::NotRegistered)
+    }
+
+    fn token_mut(&mut self, token: &ActorId) -> Result<&mut TokenInfo, RegistryError> {
+        self.tokens
+            .get_mut(token)
+            .ok_or(RegistryError::NotRegistered)
+    }
+
+    fn check_admin(&self) -> Result<(), RegistryError> {
+        if msg::source() != self.admin {
+            return Err(RegistryError::NotAdmin);
+        }
+        Ok(())
+    }
+}
+
+fn common_state() -> <RegistryMetadata as Metadata>::State {
+    let Registry { admin, tokens } = static_mut_state();
+    // Tokens are sorted by their ids so the metawasm can look them up by binary search.
+    let mut tokens: Vec<_> = tokens
+        .iter()
+        .map(|(id, info)| (*id, info.clone()))
+        .collect();
+    tokens.sort_unstable_by_key(|(id, _info)| *id);
+    IoRegistry {
+        admin: *admin,
+        tokens,
+    }
+}
+
+fn static_mut_state() -> &'static mut Registry {
+    unsafe { REGISTRY.get_or_insert(Default::default()) }
+}
+
+#[no_mangle]
+extern "C" fn state() {
+    reply(common_state()).expect(
+        "Failed to encode or reply with `<RegistryMetadata as Metadata>::State` from `state()`",
+    );
+}
+
+#[no_mangle]
+extern "C" fn metahash() {
+    let metahash: [u8; 32] = include!("../.metahash");
+    reply(metahash).expect("Failed to encode or reply with `[u8; 32]` from `metahash()`");
+}
+
+fn reply(payload: impl Encode) -> GstdResult<MessageId> {
+    msg::reply(payload, 0)
+}
+
+#[no_mangle]
+extern "C" fn handle() {
+    let action: RegistryAction = msg::load().expect("Could not load Action");
+    let registry = static_mut_state();
+    let result = match action {
+        RegistryAction::AddToken {
+            token,
+            symbol,
+            decimals,
+        } => registry.add_token(token, symbol, decimals),
+        RegistryAction::RemoveToken(token) => registry.remove_token(token),
+        RegistryAction::SetEnabled { token, enabled } => registry.set_enabled(token, enabled),
+        RegistryAction::IsAccepted(token) => {
+            Ok(RegistryEvent::Accepted(registry.is_accepted(&token)))
+        }
+        RegistryAction::TokenInfo(token) => registry.token_info(&token),
+    };
+    reply(result).expect("Failed to encode or reply with `Result<RegistryEvent, RegistryError>`");
+}
+
+#[no_mangle]
+extern "C" fn init() {
+    let config: InitRegistry = msg::load().expect("Unable to decode InitRegistry");
+    if config.admin == ZERO_ID {
+        panic!("Admin can't be zero address");
+    }
+    let registry = Registry {
+        admin: config.admin,
+        ..Default::default()
+    };
+    unsafe { REGISTRY = Some(registry) };
+}
@@ -0,0 +1,158 @@
+use gstd::{prelude::*, Encode};
+use gtest::{Program, System};
+use registry_io::*;
+const USERS: &[u64] = &[3, 4, 5];
+const TOKENS: &[u64] = &[10, 11];
+
+fn ok(event: RegistryEvent) -> Vec<u8> {
+    Ok::<RegistryEvent, RegistryError>(event).encode()
+}
+
+fn err(error: RegistryError) -> Vec<u8> {
+    Err::<RegistryEvent, RegistryError>(error).encode()
+}
+
+fn info(symbol: &str, enabled: bool) -> TokenInfo {
+    TokenInfo {
+        symbol: String::from(symbol),
+        decimals: 18,
+        enabled,
+    }
+}
+
+fn init_with_token(sys: &System) {
+    sys.init_logger();
+
+    let registry = Program::current(sys);
+
+    let res = registry.send(
+        USERS[0],
+        InitRegistry {
+            admin: USERS[0].into(),
+        },
+    );
+    assert!(res.log().is_empty());
+
+    let res = registry.send(
+        USERS[0],
+        RegistryAction::AddToken {
+            token: TOKENS[0].into(),
+            symbol: String::from("MTK"),
+            decimals: 18,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(RegistryEvent::TokenAdded {
+            token: TOKENS[0].into(),
+            info: info("MTK", true),
+        })
+    )));
+}
+
+#[test]
+fn add_token() {
+    let sys = System::new();
+    init_with_token(&sys);
+    let registry = sys.get_program(1);
+
+    let res = registry.send(USERS[1], RegistryAction::IsAccepted(TOKENS[0].into()));
+    assert!(res.contains(&(USERS[1], ok(RegistryEvent::Accepted(true)))));
+    let res = registry.send(USERS[1], RegistryAction::TokenInfo(TOKENS[0].into()));
+    assert!(res.contains(&(USERS[1], ok(RegistryEvent::TokenInfo(info("MTK", true))))));
+
+    // unknown tokens aren't accepted
+    let res = registry.send(USERS[1], RegistryAction::IsAccepted(TOKENS[1].into()));
+    assert!(res.contains(&(USERS[1], ok(RegistryEvent::Accepted(false)))));
+}
+
+#[test]
+fn add_token_failures() {
+    let sys = System::new();
+    init_with_token(&sys);
+    let registry = sys.get_program(1);
+
+    // must fail since only the admin can add tokens
+    let res = registry.send(
+        USERS[1],
+        RegistryAction::AddToken {
+            token: TOKENS[1].into(),
+            symbol: String::from("USD"),
+            decimals: 6,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(RegistryError::NotAdmin))));
+
+    // must fail since the token is already registered
+    let res = registry.send(
+        USERS[0],
+        RegistryAction::AddToken {
+            token: TOKENS[0].into(),
+            symbol: String::from("MTK"),
+            decimals: 18,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(RegistryError::AlreadyRegistered))));
+
+    // must fail since the token can't be the zero address
+    let res = registry.send(
+        USERS[0],
+        RegistryAction::AddToken {
+            token: 0.into(),
+            symbol: String::from("USD"),
+            decimals: 6,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(RegistryError::ZeroAddress))));
+}
+
+#[test]
+fn enable_and_remove_token() {
+    let sys = System::new();
+    init_with_token(&sys);
+    let registry = sys.get_program(1);
+
+    // must fail since only the admin can disable tokens
+    let res = registry.send(
+        USERS[1],
+        RegistryAction::SetEnabled {
+            token: TOKENS[0].into(),
+            enabled: false,
+        },
+    );
+    assert!(res.contains(&(USERS[1], err(RegistryError::NotAdmin))));
+
+    let res = registry.send(
+        USERS[0],
+        RegistryAction::SetEnabled {
+            token: TOKENS[0].into(),
+            enabled: false,
+        },
+    );
+    assert!(res.contains(&(
+        USERS[0],
+        ok(RegistryEvent::EnabledUpdated {
+            token: TOKENS[0].into(),
+            enabled: false,
+        })
+    )));
+
+    // disabled tokens aren't accepted
+    let res = registry.send(USERS[1], RegistryAction::IsAccepted(TOKENS[0].into()));
+    assert!(res.contains(&(USERS[1], ok(RegistryEvent::Accepted(false)))));
+
+    let res = registry.send(USERS[0], RegistryAction::RemoveToken(TOKENS[0].into()));
+    assert!(res.contains(&(USERS[0], ok(RegistryEvent::TokenRemoved(TOKENS[0].into())))));
+
+    // must fail since the token was removed
+    let res = registry.send(USERS[0], RegistryAction::RemoveToken(TOKENS[0].into()));
+    assert!(res.contains(&(USERS[0], err(RegistryError::NotRegistered))));
+    let res = registry.send(
+        USERS[0],
+        RegistryAction::SetEnabled {
+            token: TOKENS[0].into(),
+            enabled: true,
+        },
+    );
+    assert!(res.contains(&(USERS[0], err(RegistryError::NotRegistered))));
+}
@@ -0,0 +1,46 @@
+#![no_std]
+
+use gmeta::{metawasm, Metadata};
+use gstd::{prelude::*, ActorId};
+use registry_io::*;
+
+#[metawasm]
+pub mod metafns {
+    pub type State = <RegistryMetadata as Metadata>::State;
+
+    pub fn admin(state: State) -> ActorId {
+        state.admin
+    }
+
+    pub fn tokens(state: State) -> Vec<(ActorId, TokenInfo)> {
+        state.tokens
+    }
+
+    pub fn accepted_tokens(state: State) -> Vec<ActorId> {
+        state
+            .tokens
+            .into_iter()
+            .filter(|(_id, info)| info.enabled)
+            .map(|(id, _info)| id)
+            .collect()
+    }
+
+    pub fn token_info(state: State, token: ActorId) -> Option<TokenInfo> {
+        token_helper(&state, &token)
+    }
+
+    pub fn is_accepted(state: State, token: ActorId) -> bool {
+        token_helper(&state, &token).map_or(false, |info| info.enabled)
+    }
+}
+
+fn token_helper(
+    state: &<RegistryMetadata as Metadata>::State,
+    token: &ActorId,
+) -> Option<TokenInfo> {
+    state
+        .tokens
+        .binary_search_by_key(token, |(id, _info)| *id)
+        .ok()
+        .map(|index| state.tokens[index].1.clone())
+}